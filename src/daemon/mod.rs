@@ -0,0 +1,139 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+pub mod netlink;
+
+type SharedProgramOptions = super::option::SharedProgramOptions;
+
+/// Notify daemon to run detection at once, used by watchers of detectors.
+pub type Trigger = futures::channel::mpsc::UnboundedSender<()>;
+
+/// Decide how long to wait before the next detection in daemon mode.
+pub struct Scheduler {
+    interval: Duration,
+    interval_jitter: Duration,
+    retry_interval: Duration,
+    failed_count: u32,
+}
+
+impl Scheduler {
+    pub fn new(options: &SharedProgramOptions) -> Self {
+        Scheduler {
+            interval: options.interval,
+            interval_jitter: options.interval_jitter,
+            retry_interval: options.retry_interval,
+            failed_count: 0,
+        }
+    }
+
+    pub fn failed_count(&self) -> u32 {
+        self.failed_count
+    }
+
+    /// Delay before next run, without jitter.
+    /// Continuous failures double the retry delay, but never wait longer than the interval.
+    fn base_delay(&mut self, success: bool) -> Duration {
+        if success {
+            self.failed_count = 0;
+            return self.interval;
+        }
+
+        self.failed_count = self.failed_count.saturating_add(1);
+        let factor = 1_u32 << (self.failed_count - 1).min(16);
+        std::cmp::min(self.retry_interval.saturating_mul(factor), self.interval)
+    }
+
+    pub fn next_delay(&mut self, success: bool) -> Duration {
+        let base = self.base_delay(success);
+        let jitter_ms = self.interval_jitter.as_millis() as u64;
+        if jitter_ms == 0 {
+            base
+        } else {
+            base + Duration::from_millis(random_u64() % (jitter_ms + 1))
+        }
+    }
+}
+
+pub fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish()
+}
+
+/// Resolve when SIGINT or SIGTERM(on unix) is received.
+pub async fn shutdown_signal(options: SharedProgramOptions) {
+    let logger = options.create_logger("Daemon");
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = match signal(SignalKind::terminate()) {
+            Ok(s) => s,
+            Err(e) => {
+                error!(logger, "Listen SIGTERM failed, error: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                info!(logger, "Received SIGINT, shutting down");
+                return;
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => info!(logger, "Received SIGINT, shutting down"),
+            _ = sigterm.recv() => info!(logger, "Received SIGTERM, shutting down"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(logger, "Listen ctrl-c failed, error: {}", e);
+            futures::future::pending::<()>().await;
+        }
+        info!(logger, "Received ctrl-c, shutting down");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(interval: u64, jitter: u64, retry: u64) -> Scheduler {
+        Scheduler {
+            interval: Duration::from_secs(interval),
+            interval_jitter: Duration::from_secs(jitter),
+            retry_interval: Duration::from_secs(retry),
+            failed_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_backoff_on_failures() {
+        let mut s = scheduler(300, 0, 30);
+        assert_eq!(s.next_delay(false), Duration::from_secs(30));
+        assert_eq!(s.next_delay(false), Duration::from_secs(60));
+        assert_eq!(s.next_delay(false), Duration::from_secs(120));
+        assert_eq!(s.next_delay(false), Duration::from_secs(240));
+        assert_eq!(s.next_delay(false), Duration::from_secs(300));
+        assert_eq!(s.failed_count(), 5);
+        assert_eq!(s.next_delay(true), Duration::from_secs(300));
+        assert_eq!(s.failed_count(), 0);
+        assert_eq!(s.next_delay(false), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_jitter_range() {
+        let mut s = scheduler(60, 5, 10);
+        for _ in 0..100 {
+            let delay = s.next_delay(true);
+            assert!(delay >= Duration::from_secs(60));
+            assert!(delay <= Duration::from_secs(65));
+        }
+    }
+}
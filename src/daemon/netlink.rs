@@ -0,0 +1,139 @@
+use std::io;
+
+use super::Trigger;
+
+/// Types of netlink messages in buffer read from netlink socket.
+#[cfg(any(target_os = "linux", test))]
+fn parse_message_types(buffer: &[u8]) -> Vec<u16> {
+    // len(4) + type(2) + flags(2) + seq(4) + pid(4)
+    const MESSAGE_HEADER_LENGTH: usize = 16;
+
+    let mut ret = vec![];
+    let mut offset = 0;
+    while offset + MESSAGE_HEADER_LENGTH <= buffer.len() {
+        let length =
+            u32::from_ne_bytes(buffer[offset..offset + 4].try_into().unwrap_or_default()) as usize;
+        if length < MESSAGE_HEADER_LENGTH {
+            break;
+        }
+        ret.push(u16::from_ne_bytes(
+            buffer[offset + 4..offset + 6]
+                .try_into()
+                .unwrap_or_default(),
+        ));
+        // messages are aligned to 4 bytes
+        offset += (length + 3) & !3;
+    }
+    ret
+}
+
+/// Listen RTM_NEWADDR and RTM_DELADDR of rtnetlink, and notify `trigger` when addresses of
+/// network interfaces are changed.
+#[cfg(target_os = "linux")]
+pub fn watch_address_changes(trigger: Trigger) -> io::Result<()> {
+    // SAFETY: the socket is owned by the watching thread and closed when it exits.
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: sockaddr_nl is plain old data and valid when zeroed.
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = (libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+    // SAFETY: addr is valid during this call.
+    let res = unsafe {
+        libc::bind(
+            fd,
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if res < 0 {
+        let e = io::Error::last_os_error();
+        // SAFETY: fd is not used after closed.
+        unsafe { libc::close(fd) };
+        return Err(e);
+    }
+
+    std::thread::spawn(move || {
+        let mut buffer = [0_u8; 8192];
+        loop {
+            // SAFETY: buffer is valid for writing of its length.
+            let size = unsafe {
+                libc::recv(
+                    fd,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                    0,
+                )
+            };
+            if size < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                // ENOBUFS means some messages are dropped, addresses may be changed
+                if e.raw_os_error() == Some(libc::ENOBUFS) {
+                    if trigger.unbounded_send(()).is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                break;
+            }
+
+            let changed = parse_message_types(&buffer[..size as usize])
+                .iter()
+                .any(|t| *t == libc::RTM_NEWADDR || *t == libc::RTM_DELADDR);
+            if changed && trigger.unbounded_send(()).is_err() {
+                break;
+            }
+        }
+
+        // SAFETY: fd is not used after closed.
+        unsafe { libc::close(fd) };
+    });
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn watch_address_changes(_: Trigger) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "netlink is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(message_type: u16, payload_length: usize) -> Vec<u8> {
+        let length = 16 + payload_length;
+        let mut ret = (length as u32).to_ne_bytes().to_vec();
+        ret.extend_from_slice(&message_type.to_ne_bytes());
+        ret.resize(length, 0);
+        ret.resize((length + 3) & !3, 0);
+        ret
+    }
+
+    #[test]
+    fn test_parse_message_types() {
+        let mut buffer = message(20, 25);
+        buffer.extend(message(21, 8));
+        buffer.extend(message(16, 0));
+        assert_eq!(parse_message_types(&buffer), vec![20, 21, 16]);
+
+        // Truncated and invalid messages
+        assert_eq!(parse_message_types(&buffer[..10]), Vec::<u16>::new());
+        assert_eq!(parse_message_types(&[0_u8; 32]), Vec::<u16>::new());
+    }
+}
@@ -0,0 +1,192 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::time::Duration;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::option;
+use super::{AddressExtractor, Detector, DetectorResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+
+#[derive(Default)]
+pub struct CommandDetector {
+    commands: Vec<String>,
+    timeout: Option<Duration>,
+    extractor: AddressExtractor,
+    ips: Vec<Record>,
+}
+
+fn shell_command(command: &str) -> tokio::process::Command {
+    #[cfg(windows)]
+    let mut ret = {
+        let mut ret = tokio::process::Command::new("cmd");
+        ret.arg("/C").arg(command);
+        ret
+    };
+    #[cfg(not(windows))]
+    let mut ret = {
+        let mut ret = tokio::process::Command::new("sh");
+        ret.arg("-c").arg(command);
+        ret
+    };
+
+    ret.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    ret
+}
+
+/// Run command by shell and return the stdout, the command is killed on timeout.
+async fn run_command(command: &str, timeout: Duration) -> Result<String, String> {
+    let child = shell_command(command).spawn().map_err(|e| e.to_string())?;
+    let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(res) => res.map_err(|e| e.to_string())?,
+        Err(_) => return Err(format!("timeout after {}ms", timeout.as_millis())),
+    };
+
+    if !output.status.success() {
+        return Err(format!(
+            "{}, stderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl CommandDetector {
+    async fn run_commands(&mut self, options: SharedProgramOptions) -> DetectorResult<'_> {
+        let logger = options.create_logger("CommandDetector");
+        self.ips.clear();
+
+        let timeout = self.timeout.unwrap_or(options.timeout);
+        let results = future::join_all(self.commands.iter().map(|command| {
+            debug!(logger, "Running command {} to get my address", command);
+            run_command(command, timeout)
+        }))
+        .await;
+
+        for (command, result) in self.commands.iter().zip(results) {
+            let output = match result {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(logger, "Run command {} failed, {}", command, e);
+                    continue;
+                }
+            };
+
+            match self.extractor.extract(&output) {
+                Ok(addrs) => {
+                    for addr in addrs {
+                        debug!(
+                            logger,
+                            "Detect my address {} from command {}", addr, command
+                        );
+                        let record = match addr {
+                            IpAddr::V4(ipv4) => Record::A(ipv4),
+                            IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
+                        };
+                        if !self.ips.contains(&record) {
+                            self.ips.push(record);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        logger,
+                        "Extract ip address from output of command {} failed, {}", command, e
+                    );
+                }
+            }
+        }
+
+        if self.ips.is_empty() {
+            Err(())
+        } else {
+            Ok(&self.ips)
+        }
+    }
+}
+
+impl Detector for CommandDetector {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn initialize(&mut self, app: Command) -> Command {
+        let app = AddressExtractor::initialize(app, self.name());
+        app.arg(
+            Arg::new("command")
+                .long("command")
+                .value_name("COMMAND")
+                .action(ArgAction::Append)
+                .help("Get ip from stdout of command, which is run by sh -c(or cmd /C on Windows)"),
+        )
+        .arg(
+            Arg::new("command-timeout")
+                .long("command-timeout")
+                .value_name("TIMEOUT")
+                .help("Set timeout in miliseconds of command, --timeout is used if it's not set"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let logger = options.create_logger("CommandDetector");
+        self.commands = option::unwraper_multiple_values(matches, "command", &logger, "command");
+
+        let timeout: u64 = option::unwraper_from_str_or(matches, "command-timeout", 0);
+        self.timeout = if timeout > 0 {
+            Some(Duration::from_millis(timeout))
+        } else {
+            None
+        };
+
+        self.extractor.parse_options(matches, self.name(), &logger);
+    }
+
+    fn run<'a, 'b>(
+        &'a mut self,
+        options: &mut SharedProgramOptions,
+    ) -> BoxFuture<'b, DetectorResult<'a>>
+    where
+        'a: 'b,
+    {
+        if self.commands.is_empty() {
+            future::ready(Err(())).boxed()
+        } else {
+            self.run_commands(options.clone()).boxed()
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_command() {
+        async_std::task::block_on(async {
+            let timeout = Duration::from_secs(3);
+            assert_eq!(
+                run_command("echo 1.2.3.4; echo 2001:db8::1", timeout).await,
+                Ok(String::from("1.2.3.4\n2001:db8::1\n"))
+            );
+
+            let error = run_command("echo failed >&2; exit 3", timeout)
+                .await
+                .unwrap_err();
+            assert!(error.contains("3"));
+            assert!(error.contains("failed"));
+
+            let error = run_command("sleep 5", Duration::from_millis(200))
+                .await
+                .unwrap_err();
+            assert!(error.starts_with("timeout"));
+        });
+    }
+}
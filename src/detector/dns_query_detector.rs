@@ -0,0 +1,220 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use std::net::{IpAddr, SocketAddr};
+
+use async_std::net::ToSocketAddrs;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::daemon;
+use super::super::dns;
+use super::super::option;
+use super::{AddressExtractor, Detector, DetectorResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+
+const DNS_DEFAULT_PORT: u16 = 53;
+
+#[derive(Default)]
+pub struct DnsQueryDetector {
+    name: String,
+    types: Vec<u16>,
+    class: u16,
+    servers: Vec<String>,
+    ips: Vec<Record>,
+}
+
+/// Addresses in A or AAAA records, or TXT records which contain only addresses.
+fn parse_answers(response: &dns::Message, qtype: u16) -> Vec<IpAddr> {
+    let extractor = AddressExtractor::default();
+    let mut ret = vec![];
+    for answer in response.answers.iter().filter(|a| a.rtype == qtype) {
+        if let Some(addr) = answer.address() {
+            ret.push(addr);
+        }
+        for txt in answer.txt().unwrap_or_default() {
+            if let Ok(addrs) = extractor.extract(&txt) {
+                ret.extend(addrs);
+            }
+        }
+    }
+    ret
+}
+
+impl DnsQueryDetector {
+    async fn query_servers(&mut self, options: SharedProgramOptions) -> DetectorResult<'_> {
+        let logger = options.create_logger("DnsQueryDetector");
+        self.ips.clear();
+
+        let mut servers: Vec<(String, SocketAddr)> = vec![];
+        for server in &self.servers {
+            let (host, port) = option::split_host_port(server, DNS_DEFAULT_PORT);
+            match (host.as_str(), port).to_socket_addrs().await {
+                Ok(addrs) => servers.extend(addrs.map(|addr| (server.clone(), addr))),
+                Err(e) => {
+                    error!(logger, "Resolve DNS server {} failed, error: {}", server, e);
+                }
+            }
+        }
+
+        let mut requests = vec![];
+        for (server, addr) in &servers {
+            for qtype in &self.types {
+                let request = dns::Message::query(
+                    daemon::random_u64() as u16,
+                    &self.name,
+                    *qtype,
+                    self.class,
+                );
+                match request.encode() {
+                    Ok(data) => requests.push((server, *addr, *qtype, data)),
+                    Err(e) => {
+                        error!(
+                            logger,
+                            "Encode DNS query of {} failed, error: {}", self.name, e
+                        );
+                        return Err(());
+                    }
+                }
+            }
+        }
+
+        let results = future::join_all(requests.iter().map(|(_, addr, _, data)| {
+            debug!(logger, "Sending DNS query of {} to {}", self.name, addr);
+            dns::exchange(*addr, data, options.timeout)
+        }))
+        .await;
+
+        for ((server, addr, qtype, _), result) in requests.iter().zip(results) {
+            let response = match result {
+                Ok(response) => response,
+                Err(e) => {
+                    error!(
+                        logger,
+                        "Query DNS server {}({}) failed, error: {}", server, addr, e
+                    );
+                    continue;
+                }
+            };
+            if response.rcode() != 0 {
+                error!(
+                    logger,
+                    "Query {} from DNS server {}({}) failed, rcode: {}",
+                    self.name,
+                    server,
+                    addr,
+                    response.rcode()
+                );
+                continue;
+            }
+
+            for addr in parse_answers(&response, *qtype) {
+                let addr = addr.to_canonical();
+                debug!(
+                    logger,
+                    "Detect my address {} from DNS server {}", addr, server
+                );
+                let record = match addr {
+                    IpAddr::V4(ipv4) => Record::A(ipv4),
+                    IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
+                };
+                if !self.ips.contains(&record) {
+                    self.ips.push(record);
+                }
+            }
+        }
+
+        if self.ips.is_empty() {
+            error!(
+                logger,
+                "No ip address found in DNS answers of {}", self.name
+            );
+            Err(())
+        } else {
+            Ok(&self.ips)
+        }
+    }
+}
+
+impl Detector for DnsQueryDetector {
+    fn name(&self) -> &'static str {
+        "dns-query"
+    }
+
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("dns-query-name")
+                .long("dns-query-name")
+                .value_name("DOMAIN NAME")
+                .requires("dns-query-server")
+                .help("Get ip by DNS query of this name(myip.opendns.com for example)"),
+        )
+        .arg(
+            Arg::new("dns-query-type")
+                .long("dns-query-type")
+                .value_name("TYPE")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .default_value("A")
+                .ignore_case(true)
+                .value_parser(["A", "AAAA", "TXT"])
+                .help("Set record types of DNS query"),
+        )
+        .arg(
+            Arg::new("dns-query-class")
+                .long("dns-query-class")
+                .value_name("CLASS")
+                .default_value("IN")
+                .ignore_case(true)
+                .value_parser(["IN", "CH"])
+                .help("Set class of DNS query"),
+        )
+        .arg(
+            Arg::new("dns-query-server")
+                .long("dns-query-server")
+                .value_name("HOST:PORT")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Send DNS query to these servers(resolver1.opendns.com for example), default port is 53"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let logger = options.create_logger("DnsQueryDetector");
+        self.name = option::unwraper_option_or(matches, "dns-query-name", String::default());
+        self.servers =
+            option::unwraper_multiple_values(matches, "dns-query-server", &logger, "server");
+        self.types = option::unwraper_multiple_values::<String, _, _>(
+            matches,
+            "dns-query-type",
+            &logger,
+            "type",
+        )
+        .iter()
+        .filter_map(|t| dns::parse_type(t).ok())
+        .collect();
+        self.types.sort_unstable();
+        self.types.dedup();
+        self.class = dns::parse_class(&option::unwraper_option_or(
+            matches,
+            "dns-query-class",
+            String::from("IN"),
+        ))
+        .unwrap_or(dns::CLASS_IN);
+    }
+
+    fn run<'a, 'b>(
+        &'a mut self,
+        options: &mut SharedProgramOptions,
+    ) -> BoxFuture<'b, DetectorResult<'a>>
+    where
+        'a: 'b,
+    {
+        if self.name.is_empty() || self.servers.is_empty() {
+            future::ready(Err(())).boxed()
+        } else {
+            self.query_servers(options.clone()).boxed()
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+extern crate clap;
+use clap::{Arg, ArgMatches, Command};
+
+use regex::Regex;
+
+use super::super::option;
+
+#[derive(Debug, Clone, Default)]
+enum ExtractMode {
+    /// Trimmed content, multiple addresses can be separated by whitespaces or commas.
+    #[default]
+    Text,
+    /// All matches of regex, the first capture group is used if there is one.
+    Regex(Regex),
+    /// JSON pointer(`/data/ip`) or dotted path(`data.ip`) to a string or an array of strings.
+    JsonPointer(String),
+}
+
+/// Extract ip addresses from content, options are registered with a prefix, `--<PREFIX>-regex` for example.
+#[derive(Debug, Clone, Default)]
+pub struct AddressExtractor {
+    mode: ExtractMode,
+    logger: Option<slog::Logger>,
+}
+
+fn parse_address(input: &str) -> Result<IpAddr, String> {
+    // IPv4-mapped IPv6 address is returned by some dual-stack servers
+    IpAddr::from_str(input.trim())
+        .map(|addr| addr.to_canonical())
+        .map_err(|e| format!("invalid ip address {:?}, {}", input.trim(), e))
+}
+
+fn parse_text(input: &str, ret: &mut Vec<IpAddr>) -> Result<(), String> {
+    for token in input
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+    {
+        ret.push(parse_address(token)?);
+    }
+    Ok(())
+}
+
+impl AddressExtractor {
+    pub fn initialize(app: Command, prefix: &'static str) -> Command {
+        app.arg(
+            Arg::new(format!("{}-regex", prefix))
+                .long(format!("{}-regex", prefix))
+                .value_name("REGEX")
+                .conflicts_with(format!("{}-json-pointer", prefix))
+                .value_parser(|s: &str| Regex::new(s))
+                .help("Extract ip addresses by regex, the first capture group is used if there is one"),
+        )
+        .arg(
+            Arg::new(format!("{}-json-pointer", prefix))
+                .long(format!("{}-json-pointer", prefix))
+                .value_name("JSON POINTER")
+                .help("Extract ip addresses from JSON by pointer(/data/ip) or path(data.ip)"),
+        )
+    }
+
+    pub fn parse_options(&mut self, matches: &ArgMatches, prefix: &str, logger: &slog::Logger) {
+        // Invalid regex is rejected by value parser
+        let regex = matches.get_one::<Regex>(&format!("{}-regex", prefix));
+        let json_pointer = option::unwraper_option_or(
+            matches,
+            format!("{}-json-pointer", prefix),
+            String::default(),
+        );
+
+        self.logger = Some(logger.clone());
+        self.mode = if let Some(r) = regex {
+            ExtractMode::Regex(r.clone())
+        } else if json_pointer.is_empty() {
+            ExtractMode::Text
+        } else if json_pointer.starts_with('/') {
+            ExtractMode::JsonPointer(json_pointer)
+        } else {
+            ExtractMode::JsonPointer(format!("/{}", json_pointer.replace('.', "/")))
+        };
+    }
+
+    /// Extract all ip addresses from content, duplicated addresses are removed.
+    pub fn extract(&self, content: &str) -> Result<Vec<IpAddr>, String> {
+        let mut ret = vec![];
+        match self.mode {
+            ExtractMode::Text => parse_text(content, &mut ret)?,
+            ExtractMode::Regex(ref r) => {
+                // Matches which are not ip addresses are skipped
+                for caps in r.captures_iter(content) {
+                    if let Some(m) = caps.get(1).or_else(|| caps.get(0)) {
+                        match parse_address(m.as_str()) {
+                            Ok(addr) => ret.push(addr),
+                            Err(e) => {
+                                if let Some(ref logger) = self.logger {
+                                    debug!(logger, "Skip match of extract regex, {}", e);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            ExtractMode::JsonPointer(ref pointer) => {
+                let value: serde_json::Value =
+                    serde_json::from_str(content).map_err(|e| e.to_string())?;
+                match value.pointer(pointer) {
+                    Some(serde_json::Value::String(s)) => parse_text(s, &mut ret)?,
+                    Some(serde_json::Value::Array(arr)) => {
+                        for v in arr {
+                            match v.as_str() {
+                                Some(s) => ret.push(parse_address(s)?),
+                                None => return Err(format!("{} is not a string", v)),
+                            }
+                        }
+                    }
+                    Some(v) => {
+                        return Err(format!("{} of {} is not a string or array", pointer, v))
+                    }
+                    None => return Err(format!("{} not found", pointer)),
+                }
+            }
+        }
+
+        let mut unique = vec![];
+        for addr in ret {
+            if !unique.contains(&addr) {
+                unique.push(addr);
+            }
+        }
+
+        if unique.is_empty() {
+            Err(String::from("no ip address found"))
+        } else {
+            Ok(unique)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extract(extractor: &AddressExtractor, content: &str) -> Vec<String> {
+        extractor
+            .extract(content)
+            .unwrap()
+            .iter()
+            .map(|a| a.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_text() {
+        let extractor = AddressExtractor::default();
+        assert_eq!(extract(&extractor, "1.2.3.4\n"), vec!["1.2.3.4"]);
+        assert_eq!(
+            extract(&extractor, " ::ffff:1.2.3.4, 2001:db8::1 1.2.3.4"),
+            vec!["1.2.3.4", "2001:db8::1"]
+        );
+        assert!(extractor.extract("<html></html>").is_err());
+        assert!(extractor.extract("  ").is_err());
+    }
+
+    #[test]
+    fn test_regex() {
+        let extractor = AddressExtractor {
+            mode: ExtractMode::Regex(Regex::new(r"WAN IP: ([0-9a-f.:]+)").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract(
+                &extractor,
+                "<td>WAN IP: 1.2.3.4</td><td>WAN IP: 2001:db8::1</td><td>LAN IP: 192.168.1.1</td>"
+            ),
+            vec!["1.2.3.4", "2001:db8::1"]
+        );
+        assert_eq!(
+            extract(&extractor, "WAN IP: ... WAN IP: 1.2.3.4 WAN IP: 1.2.3.4.5"),
+            vec!["1.2.3.4"]
+        );
+        assert!(extractor.extract("nothing").is_err());
+        assert!(extractor.extract("WAN IP: ...").is_err());
+
+        let app = AddressExtractor::initialize(Command::new("test"), "test");
+        let matches = app
+            .clone()
+            .try_get_matches_from(["test", "--test-regex", r"IP: (\d+"]);
+        assert!(matches.is_err());
+        let matches = app
+            .try_get_matches_from(["test", "--test-regex", r"IP: ([0-9.]+)"])
+            .unwrap();
+        let mut extractor = AddressExtractor::default();
+        extractor.parse_options(&matches, "test", &slog::Logger::root(slog::Discard, o!()));
+        assert_eq!(extract(&extractor, "IP: 1.2.3.4"), vec!["1.2.3.4"]);
+    }
+
+    #[test]
+    fn test_json_pointer() {
+        let extractor = AddressExtractor {
+            mode: ExtractMode::JsonPointer(String::from("/ip")),
+            ..Default::default()
+        };
+        assert_eq!(extract(&extractor, r#"{"ip":"1.2.3.4"}"#), vec!["1.2.3.4"]);
+        assert!(extractor.extract(r#"{"addr":"1.2.3.4"}"#).is_err());
+
+        let extractor = AddressExtractor {
+            mode: ExtractMode::JsonPointer(String::from("/data/ips")),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract(&extractor, r#"{"data":{"ips":["1.2.3.4","2001:db8::1"]}}"#),
+            vec!["1.2.3.4", "2001:db8::1"]
+        );
+    }
+}
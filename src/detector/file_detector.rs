@@ -0,0 +1,277 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::option;
+use super::{AddressExtractor, Detector, DetectorResult, Record, Trigger};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+
+#[derive(Default)]
+pub struct FileDetector {
+    paths: Vec<PathBuf>,
+    extractor: AddressExtractor,
+    ips: Vec<Record>,
+}
+
+impl FileDetector {
+    async fn read_files(&mut self, options: SharedProgramOptions) -> DetectorResult<'_> {
+        let logger = options.create_logger("FileDetector");
+        self.ips.clear();
+
+        for path in &self.paths {
+            let content = match async_std::fs::read_to_string(path).await {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(logger, "Read file {} failed, error: {}", path.display(), e);
+                    continue;
+                }
+            };
+
+            match self.extractor.extract(&content) {
+                Ok(addrs) => {
+                    for addr in addrs {
+                        debug!(
+                            logger,
+                            "Detect my address {} from file {}",
+                            addr,
+                            path.display()
+                        );
+                        let record = match addr {
+                            IpAddr::V4(ipv4) => Record::A(ipv4),
+                            IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
+                        };
+                        if !self.ips.contains(&record) {
+                            self.ips.push(record);
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        logger,
+                        "Extract ip address from file {} failed, {}",
+                        path.display(),
+                        e
+                    );
+                }
+            }
+        }
+
+        if self.ips.is_empty() {
+            Err(())
+        } else {
+            Ok(&self.ips)
+        }
+    }
+}
+
+impl Detector for FileDetector {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn initialize(&mut self, app: Command) -> Command {
+        let app = AddressExtractor::initialize(app, self.name());
+        app.arg(
+            Arg::new("file")
+                .long("file")
+                .value_name("PATH")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Get ip from files, one address per line by default, files are watched in daemon mode"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let logger = options.create_logger("FileDetector");
+        self.paths = option::unwraper_multiple_values(matches, "file", &logger, "path");
+        self.extractor.parse_options(matches, self.name(), &logger);
+    }
+
+    fn watch(&mut self, options: &SharedProgramOptions, trigger: &Trigger) {
+        if self.paths.is_empty() {
+            return;
+        }
+
+        let logger = options.create_logger("FileDetector");
+        match watch_files(&self.paths, trigger.clone()) {
+            Ok(_) => {
+                debug!(logger, "Start watching changes of files");
+            }
+            Err(e) => {
+                error!(logger, "Watch changes of files failed, error: {}", e);
+            }
+        }
+    }
+
+    fn run<'a, 'b>(
+        &'a mut self,
+        options: &mut SharedProgramOptions,
+    ) -> BoxFuture<'b, DetectorResult<'a>>
+    where
+        'a: 'b,
+    {
+        if self.paths.is_empty() {
+            future::ready(Err(())).boxed()
+        } else {
+            self.read_files(options.clone()).boxed()
+        }
+    }
+}
+
+/// Watch descriptor and file name of `struct inotify_event` in buffer read from inotify.
+#[cfg(target_os = "linux")]
+fn parse_inotify_events(buffer: &[u8]) -> Vec<(i32, &[u8])> {
+    // wd(4) + mask(4) + cookie(4) + len(4) + name(len)
+    const EVENT_HEADER_LENGTH: usize = 16;
+
+    let mut ret = vec![];
+    let mut offset = 0;
+    while offset + EVENT_HEADER_LENGTH <= buffer.len() {
+        let wd = i32::from_ne_bytes(buffer[offset..offset + 4].try_into().unwrap_or_default());
+        let length = u32::from_ne_bytes(
+            buffer[offset + 12..offset + 16]
+                .try_into()
+                .unwrap_or_default(),
+        ) as usize;
+        let name_end = (offset + EVENT_HEADER_LENGTH + length).min(buffer.len());
+        let name = &buffer[offset + EVENT_HEADER_LENGTH..name_end];
+        let name_length = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+        ret.push((wd, &name[..name_length]));
+        offset += EVENT_HEADER_LENGTH + length;
+    }
+    ret
+}
+
+/// Directories of files are watched by inotify, so files replaced by rename are also noticed.
+#[cfg(target_os = "linux")]
+fn watch_files(paths: &[PathBuf], trigger: Trigger) -> io::Result<()> {
+    use std::ffi::{CString, OsString};
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+
+    // SAFETY: the descriptor is owned by the watching thread and closed when it exits.
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut watches: Vec<(i32, OsString)> = vec![];
+    for path in paths {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_name = path
+            .file_name()
+            .map(|f| f.to_os_string())
+            .unwrap_or_default();
+        let dir = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: dir is a valid C string during this call.
+        let wd = unsafe {
+            libc::inotify_add_watch(
+                fd,
+                dir.as_ptr(),
+                libc::IN_CLOSE_WRITE
+                    | libc::IN_MODIFY
+                    | libc::IN_CREATE
+                    | libc::IN_DELETE
+                    | libc::IN_MOVED_TO
+                    | libc::IN_MOVED_FROM,
+            )
+        };
+        if wd < 0 {
+            let e = io::Error::last_os_error();
+            // SAFETY: fd is not used after closed.
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        watches.push((wd, file_name));
+    }
+
+    std::thread::spawn(move || {
+        let watches: Vec<(i32, Vec<u8>)> = watches
+            .into_iter()
+            .map(|(wd, name)| (wd, name.into_vec()))
+            .collect();
+        let mut buffer = [0_u8; 4096];
+        loop {
+            // SAFETY: buffer is valid for writing of its length.
+            let size =
+                unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            if size < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                break;
+            }
+
+            let changed = parse_inotify_events(&buffer[..size as usize])
+                .iter()
+                .any(|(wd, name)| watches.iter().any(|(w, n)| w == wd && n == name));
+            if changed && trigger.unbounded_send(()).is_err() {
+                break;
+            }
+        }
+
+        // SAFETY: fd is not used after closed.
+        unsafe { libc::close(fd) };
+    });
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch_files(_: &[PathBuf], _: Trigger) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "watching files is not supported on this platform",
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_watch_file() {
+        let dir = std::env::temp_dir().join(format!("ddns-cli-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("lease");
+        std::fs::write(&path, "192.0.2.1\n").unwrap();
+
+        let (trigger, mut changes) = futures::channel::mpsc::unbounded();
+        watch_files(std::slice::from_ref(&path), trigger).unwrap();
+
+        async_std::task::block_on(async {
+            // Changes of other files are ignored
+            std::fs::write(dir.join("other"), "192.0.2.3\n").unwrap();
+            assert!(
+                async_std::future::timeout(Duration::from_millis(200), changes.next())
+                    .await
+                    .is_err()
+            );
+
+            // Replace by rename
+            let tmp_path = dir.join("lease.tmp");
+            std::fs::write(&tmp_path, "192.0.2.2\n").unwrap();
+            std::fs::rename(&tmp_path, &path).unwrap();
+            assert_eq!(
+                async_std::future::timeout(Duration::from_secs(3), changes.next())
+                    .await
+                    .unwrap(),
+                Some(())
+            );
+        });
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::option;
+use super::Record;
+
+/// Network address with prefix length, `192.168.0.0/16` or `2001:db8::/32` for example.
+/// A single address without prefix length is the same as `/32` for IPv4 or `/128` for IPv6.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix_length: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_length))
+                    .unwrap_or(0);
+                (u32::from(network) & mask) == (u32::from(*addr) & mask)
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_length))
+                    .unwrap_or(0);
+                (u128::from(network) & mask) == (u128::from(*addr) & mask)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_length) = match s.split_once('/') {
+            Some((addr, prefix_length)) => (addr, Some(prefix_length)),
+            None => (s, None),
+        };
+        let addr = IpAddr::from_str(addr.trim()).map_err(|e| e.to_string())?;
+        let max_prefix_length = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_length = match prefix_length {
+            Some(v) => v.trim().parse::<u8>().map_err(|e| e.to_string())?,
+            None => max_prefix_length,
+        };
+        if prefix_length > max_prefix_length {
+            return Err(format!("prefix length {} is too large", prefix_length));
+        }
+
+        Ok(Cidr {
+            addr,
+            prefix_length,
+        })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!("{}/{}", self.addr, self.prefix_length))
+    }
+}
+
+/// Ignore some kinds of addresses, options are registered with a prefix, `--<PREFIX>-no-private` for example.
+#[derive(Default, Debug, Clone)]
+pub struct AddressFilter {
+    ignore_link_local: bool,
+    ignore_shared: bool,
+    ignore_loopback: bool,
+    ignore_private: bool,
+    ignore_multicast: bool,
+    ignore_ula: bool,
+    ignore_documentation: bool,
+    ignore_6to4: bool,
+    ignore_teredo: bool,
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl AddressFilter {
+    pub fn initialize(app: Command, prefix: &'static str) -> Command {
+        app.arg(
+            Arg::new(format!("{}-no-link-local", prefix))
+                .long(format!("{}-no-link-local", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore link local address"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-shared", prefix))
+                .long(format!("{}-no-shared", prefix))
+                .alias(format!("{}-no-cgnat", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore shared address(100.64.0.0/10, used by CGNAT)"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-loopback", prefix))
+                .long(format!("{}-no-loopback", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore loopback address"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-private", prefix))
+                .long(format!("{}-no-private", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore private address"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-multicast", prefix))
+                .long(format!("{}-no-multicast", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore multicast address"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-ula", prefix))
+                .long(format!("{}-no-ula", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore IPv6 unique local address(fc00::/7)"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-documentation", prefix))
+                .long(format!("{}-no-documentation", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore documentation address(192.0.2.0/24, 198.51.100.0/24, 203.0.113.0/24 and 2001:db8::/32)"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-6to4", prefix))
+                .long(format!("{}-no-6to4", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore 6to4 address(2002::/16)"),
+        )
+        .arg(
+            Arg::new(format!("{}-no-teredo", prefix))
+                .long(format!("{}-no-teredo", prefix))
+                .action(ArgAction::SetTrue)
+                .help("Ignore teredo address(2001::/32)"),
+        )
+        .arg(
+            Arg::new(format!("{}-allow", prefix))
+                .long(format!("{}-allow", prefix))
+                .value_name("CIDR")
+                .num_args(1..)
+                .value_parser(|s: &str| s.parse::<Cidr>())
+                .action(ArgAction::Append)
+                .help("Only accept address in these networks"),
+        )
+        .arg(
+            Arg::new(format!("{}-deny", prefix))
+                .long(format!("{}-deny", prefix))
+                .value_name("CIDR")
+                .num_args(1..)
+                .value_parser(|s: &str| s.parse::<Cidr>())
+                .action(ArgAction::Append)
+                .help("Ignore address in these networks"),
+        )
+    }
+
+    pub fn parse_options(&mut self, matches: &ArgMatches, prefix: &str, logger: &slog::Logger) {
+        self.ignore_link_local =
+            option::unwraper_flag(matches, format!("{}-no-link-local", prefix));
+        self.ignore_shared = option::unwraper_flag(matches, format!("{}-no-shared", prefix));
+        self.ignore_loopback = option::unwraper_flag(matches, format!("{}-no-loopback", prefix));
+        self.ignore_private = option::unwraper_flag(matches, format!("{}-no-private", prefix));
+        self.ignore_multicast = option::unwraper_flag(matches, format!("{}-no-multicast", prefix));
+        self.ignore_ula = option::unwraper_flag(matches, format!("{}-no-ula", prefix));
+        self.ignore_documentation =
+            option::unwraper_flag(matches, format!("{}-no-documentation", prefix));
+        self.ignore_6to4 = option::unwraper_flag(matches, format!("{}-no-6to4", prefix));
+        self.ignore_teredo = option::unwraper_flag(matches, format!("{}-no-teredo", prefix));
+        self.allow = option::unwraper_multiple_values(
+            matches,
+            format!("{}-allow", prefix),
+            logger,
+            "network",
+        );
+        self.deny = option::unwraper_multiple_values(
+            matches,
+            format!("{}-deny", prefix),
+            logger,
+            "network",
+        );
+    }
+
+    pub fn accept(&self, addr: &IpAddr) -> bool {
+        let ignored = match addr {
+            IpAddr::V4(ipv4) => {
+                (self.ignore_link_local && ipv4.is_link_local())
+                    || (self.ignore_shared
+                        && ipv4.octets()[0] == 100
+                        && (ipv4.octets()[1] & 0b1100_0000 == 0b0100_0000))
+                    || (self.ignore_loopback && ipv4.is_loopback())
+                    || (self.ignore_private && ipv4.is_private())
+                    || (self.ignore_multicast && ipv4.is_multicast())
+                    || (self.ignore_documentation && ipv4.is_documentation())
+            }
+            IpAddr::V6(ipv6) => {
+                let segments = ipv6.segments();
+                (self.ignore_link_local && (segments[0] & 0xffc0) == 0xfe80)
+                    || (self.ignore_loopback && ipv6.is_loopback())
+                    || ((self.ignore_private || self.ignore_ula)
+                        && (segments[0] & 0xfe00) == 0xfc00)
+                    || (self.ignore_multicast && ipv6.is_multicast())
+                    || (self.ignore_documentation && segments[0] == 0x2001 && segments[1] == 0x0db8)
+                    || (self.ignore_6to4 && segments[0] == 0x2002)
+                    || (self.ignore_teredo && segments[0] == 0x2001 && segments[1] == 0)
+            }
+        };
+
+        !ignored
+            && (self.allow.is_empty() || self.allow.iter().any(|c| c.contains(addr)))
+            && !self.deny.iter().any(|c| c.contains(addr))
+    }
+
+    /// Remove ignored A and AAAA records, other records are always kept.
+    pub fn apply(&self, records: &mut Vec<Record>, logger: &slog::Logger) {
+        records.retain(|record| {
+            let addr = match record {
+                Record::A(ipv4) => IpAddr::V4(*ipv4),
+                Record::Aaaa(ipv6) => IpAddr::V6(*ipv6),
+                _ => return true,
+            };
+
+            let accept = self.accept(&addr);
+            if !accept {
+                debug!(logger, "Ignore ip address {}", addr);
+            }
+            accept
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(filter: &AddressFilter, addr: &str) -> bool {
+        filter.accept(&addr.parse().unwrap())
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr = Cidr::from_str("192.168.0.0/16").unwrap();
+        assert!(cidr.contains(&"192.168.3.4".parse().unwrap()));
+        assert!(!cidr.contains(&"192.169.0.1".parse().unwrap()));
+        assert!(!cidr.contains(&"::1".parse().unwrap()));
+
+        let cidr = Cidr::from_str("2001:db8::/32").unwrap();
+        assert!(cidr.contains(&"2001:db8:1::1".parse().unwrap()));
+        assert!(!cidr.contains(&"2001:db9::1".parse().unwrap()));
+
+        assert!(Cidr::from_str("0.0.0.0/0")
+            .unwrap()
+            .contains(&"1.2.3.4".parse().unwrap()));
+        assert_eq!(Cidr::from_str("::1").unwrap().to_string(), "::1/128");
+        assert!(Cidr::from_str("1.2.3.4/33").is_err());
+    }
+
+    #[test]
+    fn test_special_ranges() {
+        let filter = AddressFilter {
+            ignore_shared: true,
+            ignore_ula: true,
+            ignore_documentation: true,
+            ignore_6to4: true,
+            ignore_teredo: true,
+            ..Default::default()
+        };
+        assert!(!accept(&filter, "100.100.1.1"));
+        assert!(accept(&filter, "100.128.1.1"));
+        assert!(!accept(&filter, "fd00::1"));
+        assert!(!accept(&filter, "198.51.100.7"));
+        assert!(!accept(&filter, "2001:db8::1"));
+        assert!(!accept(&filter, "2002:c000:0204::1"));
+        assert!(!accept(&filter, "2001:0:4136:e378::1"));
+        assert!(accept(&filter, "2001:4860::1"));
+        assert!(accept(&filter, "10.0.0.1"));
+    }
+
+    #[test]
+    fn test_allow_and_deny() {
+        let filter = AddressFilter {
+            allow: vec![Cidr::from_str("2400::/12").unwrap()],
+            deny: vec![Cidr::from_str("2400:1111::/32").unwrap()],
+            ..Default::default()
+        };
+        assert!(accept(&filter, "2400:2222::1"));
+        assert!(!accept(&filter, "2400:1111::1"));
+        assert!(!accept(&filter, "2001:4860::1"));
+        assert!(!accept(&filter, "1.2.3.4"));
+
+        let app = AddressFilter::initialize(Command::new("test"), "test");
+        let matches =
+            app.clone()
+                .try_get_matches_from(["test", "--test-allow", "2400::/12", "2400::/129"]);
+        assert!(matches.is_err());
+        let matches = app
+            .try_get_matches_from([
+                "test",
+                "--test-allow",
+                "2400::/12",
+                "--test-deny",
+                "10.0.0.1",
+            ])
+            .unwrap();
+        let mut filter = AddressFilter::default();
+        filter.parse_options(&matches, "test", &slog::Logger::root(slog::Discard, o!()));
+        assert_eq!(filter.allow, vec![Cidr::from_str("2400::/12").unwrap()]);
+        assert_eq!(filter.deny, vec![Cidr::from_str("10.0.0.1/32").unwrap()]);
+    }
+}
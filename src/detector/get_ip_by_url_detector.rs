@@ -1,80 +1,402 @@
 use futures::future::{self, BoxFuture, FutureExt, TryFutureExt};
 
-use std::net::IpAddr;
-use std::str::FromStr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
 
 extern crate clap;
-use clap::{Arg, ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 
 use super::super::option;
-use super::{Detector, DetectorResult, Record};
+use super::{AddressExtractor, Detector, DetectorResult, Record};
 
 type SharedProgramOptions = super::SharedProgramOptions;
 type HttpMethod = super::HttpMethod;
 
+struct UrlSource {
+    url: String,
+    local_address: Option<IpAddr>,
+}
+
+#[derive(Default)]
+struct RequestOptions {
+    method: HttpMethod,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+    basic_auth: Option<(String, Option<String>)>,
+    bearer: Option<String>,
+    timeout: Option<Duration>,
+}
+
+impl RequestOptions {
+    fn build(
+        &self,
+        options: &SharedProgramOptions,
+        url: &str,
+        local_address: Option<IpAddr>,
+    ) -> reqwest::RequestBuilder {
+        let mut cli = options.http_with_local_address(self.method, url, local_address);
+        for (name, value) in &self.headers {
+            cli = cli.header(name, value);
+        }
+        if let Some(ref body) = self.body {
+            cli = cli.body(body.clone());
+        }
+        if let Some((ref username, ref password)) = self.basic_auth {
+            cli = cli.basic_auth(username, password.as_ref());
+        }
+        if let Some(ref token) = self.bearer {
+            cli = cli.bearer_auth(token);
+        }
+        if let Some(timeout) = self.timeout {
+            cli = cli.timeout(timeout);
+        }
+        cli
+    }
+}
+
 #[derive(Default)]
 pub struct GetIpByUrlDetector {
-    url: String,
+    sources: Vec<UrlSource>,
+    quorum: usize,
+    request: RequestOptions,
+    extractor: AddressExtractor,
     ips: Vec<Record>,
 }
 
+/// Addresses of each family which are accepted, and sources which disagree with them. Every
+/// source votes once for each address it reports. All addresses of a family are accepted if every
+/// source reports the same ones, otherwise only the most reported one is accepted if it's reported
+/// by at least `quorum` sources without a tie.
+fn vote<'a>(answers: &[(&'a str, IpAddr)], quorum: usize) -> (Vec<IpAddr>, Vec<(&'a str, IpAddr)>) {
+    let quorum = quorum.max(1);
+    let mut accepted: Vec<IpAddr> = vec![];
+    for is_ipv4 in [true, false] {
+        let mut sources: Vec<(&str, Vec<IpAddr>)> = vec![];
+        for (source, addr) in answers.iter().filter(|(_, a)| a.is_ipv4() == is_ipv4) {
+            match sources.iter_mut().find(|(s, _)| s == source) {
+                Some((_, addrs)) => {
+                    if !addrs.contains(addr) {
+                        addrs.push(*addr);
+                    }
+                }
+                None => sources.push((source, vec![*addr])),
+            }
+        }
+        let first = match sources.first() {
+            Some((_, addrs)) => addrs,
+            None => continue,
+        };
+
+        if sources
+            .iter()
+            .all(|(_, addrs)| addrs.len() == first.len() && addrs.iter().all(|a| first.contains(a)))
+        {
+            if sources.len() >= quorum {
+                accepted.extend(first);
+            }
+            continue;
+        }
+
+        let mut counter: Vec<(IpAddr, usize)> = vec![];
+        for addr in sources.iter().flat_map(|(_, addrs)| addrs) {
+            match counter.iter_mut().find(|(a, _)| a == addr) {
+                Some((_, count)) => *count += 1,
+                None => counter.push((*addr, 1)),
+            }
+        }
+        let max_count = counter.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        let winners: Vec<IpAddr> = counter
+            .iter()
+            .filter(|(_, count)| *count == max_count)
+            .map(|(addr, _)| *addr)
+            .collect();
+        if winners.len() == 1 && max_count >= quorum {
+            accepted.push(winners[0]);
+        }
+    }
+    let disagreed = answers
+        .iter()
+        .filter(|(_, addr)| {
+            !accepted.contains(addr) && accepted.iter().any(|a| a.is_ipv4() == addr.is_ipv4())
+        })
+        .cloned()
+        .collect();
+    (accepted, disagreed)
+}
+
+async fn request_address(
+    options: &SharedProgramOptions,
+    logger: &slog::Logger,
+    url: &str,
+    local_address: Option<IpAddr>,
+    request: &RequestOptions,
+    extractor: &AddressExtractor,
+) -> Result<Vec<IpAddr>, ()> {
+    let cli = request.build(options, url, local_address);
+
+    debug!(logger, "Sending request to {} to get my address", url);
+
+    let response = cli
+        .send()
+        .map_err(|e| {
+            error!(logger, "Send HTTP request to {} failed {}", url, e);
+            debug!(logger, "{:?}", e);
+        })
+        .await?;
+
+    let body_text = response
+        .text()
+        .map_err(|e| {
+            error!(logger, "Get HTTP response from {} failed {}", url, e);
+            debug!(logger, "{:?}", e);
+        })
+        .await?;
+
+    debug!(logger, "Detect my address: {}", body_text);
+
+    extractor.extract(&body_text).map_err(|e| {
+        error!(
+            logger,
+            "Extract ip address from HTTP body of {} failed, {}", url, e
+        );
+    })
+}
+
 impl GetIpByUrlDetector {
     // #[actix_rt::main]
-    pub async fn pull_request_content(&mut self, options: SharedProgramOptions) -> DetectorResult {
+    pub async fn pull_request_content(
+        &mut self,
+        options: SharedProgramOptions,
+    ) -> DetectorResult<'_> {
         let logger = options.create_logger("GetIpByUrlDetector");
+        self.ips.clear();
 
-        let cli = options.http(HttpMethod::Get, &self.url);
-
-        debug!(logger, "Sending request to {} to get my address", self.url);
+        let request = &self.request;
+        let extractor = &self.extractor;
+        let results = future::join_all(self.sources.iter().map(|source| {
+            let options = &options;
+            let logger = &logger;
+            async move {
+                (
+                    source,
+                    request_address(
+                        options,
+                        logger,
+                        &source.url,
+                        source.local_address,
+                        request,
+                        extractor,
+                    )
+                    .await,
+                )
+            }
+        }))
+        .await;
 
-        let response = cli
-            .send()
-            .map_err(|e| {
-                error!(logger, "Send HTTP request failed {}", e);
-                debug!(logger, "{:?}", e);
-            })
-            .await?;
+        let mut answers: Vec<(&str, IpAddr)> = vec![];
+        for (source, result) in results {
+            let addrs = match result {
+                Ok(addrs) => addrs,
+                Err(_) => continue,
+            };
 
-        let body_text = response
-            .text()
-            .map_err(|e| {
-                error!(logger, "Get HTTP response failed {}", e);
-                debug!(logger, "{:?}", e);
-            })
-            .await?;
-
-        debug!(logger, "Detect my address: {}", body_text);
-
-        match IpAddr::from_str(&body_text) {
-            Ok(addr) => {
-                let final_addr = match addr {
-                    IpAddr::V4(ipv4) => Record::A(ipv4),
-                    IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
-                };
-                self.ips.push(final_addr);
-                Ok(&self.ips)
+            for addr in addrs {
+                if let Some(local_address) = source.local_address {
+                    if local_address.is_ipv4() != addr.is_ipv4() {
+                        error!(
+                            logger,
+                            "Detect {} from {} but the connection is bound to {}",
+                            addr,
+                            source.url,
+                            local_address
+                        );
+                        continue;
+                    }
+                }
+                answers.push((&source.url, addr));
             }
-            Err(e) => {
-                error!(logger, "Parse ip address from HTTP body failed {}", e);
-                debug!(logger, "{:?}", e);
-                Err(())
+        }
+
+        let (accepted, disagreed) = vote(&answers, self.quorum);
+        for (url, addr) in disagreed {
+            warn!(
+                logger,
+                "Source {} reports {} which disagrees with others", url, addr
+            );
+        }
+        for (url, addr) in &answers {
+            if !accepted.iter().any(|a| a.is_ipv4() == addr.is_ipv4()) {
+                warn!(
+                    logger,
+                    "Source {} reports {} but no address of this family is agreed by quorum {}",
+                    url,
+                    addr,
+                    self.quorum
+                );
             }
         }
+
+        self.ips = accepted
+            .into_iter()
+            .map(|addr| match addr {
+                IpAddr::V4(ipv4) => Record::A(ipv4),
+                IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
+            })
+            .collect();
+
+        if self.ips.is_empty() {
+            Err(())
+        } else {
+            Ok(&self.ips)
+        }
     }
 }
 
 impl Detector for GetIpByUrlDetector {
+    fn name(&self) -> &'static str {
+        "get-ip-by-url"
+    }
+
     fn initialize(&mut self, app: Command) -> Command {
+        let app = AddressExtractor::initialize(app, self.name());
         app.arg(
             Arg::new("get-ip-by-url")
                 .long("get-ip-by-url")
                 .value_name("URL TO VISIT")
+                .num_args(1..)
+                .action(ArgAction::Append)
                 .help("Get ip by visit specify url(https://myip.biturl.top/ for example)"),
         )
+        .arg(
+            Arg::new("get-ipv4-by-url")
+                .long("get-ipv4-by-url")
+                .value_name("URL TO VISIT")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Get IPv4 address by visit specify url with connection bound to 0.0.0.0"),
+        )
+        .arg(
+            Arg::new("get-ipv6-by-url")
+                .long("get-ipv6-by-url")
+                .value_name("URL TO VISIT")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Get IPv6 address by visit specify url with connection bound to ::"),
+        )
+        .arg(
+            Arg::new("get-ip-by-url-quorum")
+                .long("get-ip-by-url-quorum")
+                .value_name("NUMBER")
+                .default_value("1")
+                .help("Set number of urls which must agree on addresses, all addresses of a family are used if every url reports the same ones, or else only the most reported one is used if there is no tie"),
+        )
+        .arg(
+            Arg::new("get-ip-by-url-timeout")
+                .long("get-ip-by-url-timeout")
+                .value_name("TIMEOUT")
+                .help("Set timeout in miliseconds of every url, --timeout is used if it's not set"),
+        )
+        .arg(
+            Arg::new("get-ip-by-url-method")
+                .long("get-ip-by-url-method")
+                .value_name("METHOD")
+                .default_value("GET")
+                .ignore_case(true)
+                .value_parser(["GET", "POST", "PUT", "PATCH", "DELETE"])
+                .help("Set HTTP method of requests to get ip"),
+        )
+        .arg(
+            Arg::new("get-ip-by-url-header")
+                .long("get-ip-by-url-header")
+                .value_name("NAME: VALUE")
+                .action(ArgAction::Append)
+                .help("Add HTTP header to requests to get ip"),
+        )
+        .arg(
+            Arg::new("get-ip-by-url-body")
+                .long("get-ip-by-url-body")
+                .value_name("BODY")
+                .help("Set HTTP body of requests to get ip"),
+        )
+        .arg(
+            Arg::new("get-ip-by-url-basic-auth")
+                .long("get-ip-by-url-basic-auth")
+                .value_name("USERNAME:PASSWORD")
+                .conflicts_with("get-ip-by-url-bearer")
+                .help("Set HTTP basic authentication of requests to get ip"),
+        )
+        .arg(
+            Arg::new("get-ip-by-url-bearer")
+                .long("get-ip-by-url-bearer")
+                .value_name("TOKEN")
+                .help("Set HTTP bearer token of requests to get ip"),
+        )
     }
 
-    fn parse_options(&mut self, matches: &ArgMatches, _: &mut SharedProgramOptions) {
-        self.url = option::unwraper_option_or(matches, "get-ip-by-url", String::default());
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let logger = options.create_logger("GetIpByUrlDetector");
+        for (name, local_address) in [
+            ("get-ip-by-url", None),
+            ("get-ipv4-by-url", Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))),
+            ("get-ipv6-by-url", Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED))),
+        ] {
+            for url in
+                option::unwraper_multiple_values::<String, _, _>(matches, name, &logger, "url")
+            {
+                self.sources.push(UrlSource { url, local_address });
+            }
+        }
+
+        self.extractor.parse_options(matches, self.name(), &logger);
+
+        self.quorum = option::unwraper_from_str_or(matches, "get-ip-by-url-quorum", 1);
+        let timeout: u64 = option::unwraper_from_str_or(matches, "get-ip-by-url-timeout", 0);
+        self.request.timeout = if timeout > 0 {
+            Some(Duration::from_millis(timeout))
+        } else {
+            None
+        };
+        self.request.method =
+            option::unwraper_from_str_or(matches, "get-ip-by-url-method", HttpMethod::Get);
+        self.request.headers = option::unwraper_multiple_values::<String, _, _>(
+            matches,
+            "get-ip-by-url-header",
+            &logger,
+            "header",
+        )
+        .into_iter()
+        .filter_map(|header| match header.split_once(':') {
+            Some((name, value)) => Some((name.trim().to_string(), value.trim().to_string())),
+            None => {
+                error!(
+                    logger,
+                    "Invalid HTTP header {}, NAME: VALUE is required", header
+                );
+                None
+            }
+        })
+        .collect();
+
+        let body = option::unwraper_option_or(matches, "get-ip-by-url-body", String::default());
+        self.request.body = if body.is_empty() { None } else { Some(body) };
+
+        let basic_auth =
+            option::unwraper_option_or(matches, "get-ip-by-url-basic-auth", String::default());
+        self.request.basic_auth = if basic_auth.is_empty() {
+            None
+        } else {
+            Some(match basic_auth.split_once(':') {
+                Some((username, password)) => (username.to_string(), Some(password.to_string())),
+                None => (basic_auth, None),
+            })
+        };
+
+        let bearer = option::unwraper_option_or(matches, "get-ip-by-url-bearer", String::default());
+        self.request.bearer = if bearer.is_empty() {
+            None
+        } else {
+            Some(bearer)
+        };
     }
 
     fn run<'a, 'b>(
@@ -84,10 +406,183 @@ impl Detector for GetIpByUrlDetector {
     where
         'a: 'b,
     {
-        if self.url.is_empty() {
+        if self.sources.is_empty() {
             future::ready(Err(())).boxed()
         } else {
             self.pull_request_content(options.clone()).boxed()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_vote() {
+        let a: IpAddr = "1.2.3.4".parse().unwrap();
+        let b: IpAddr = "5.6.7.8".parse().unwrap();
+        let c: IpAddr = "2001:db8::1".parse().unwrap();
+        let answers = vec![("s1", a), ("s2", b), ("s3", a), ("s4", c)];
+
+        let (accepted, disagreed) = vote(&answers, 2);
+        assert_eq!(accepted, vec![a]);
+        assert_eq!(disagreed, vec![("s2", b)]);
+
+        // Only the most reported address of each family is accepted
+        let (accepted, disagreed) = vote(&answers, 1);
+        assert_eq!(accepted, vec![a, c]);
+        assert_eq!(disagreed, vec![("s2", b)]);
+
+        let (accepted, _) = vote(&answers, 3);
+        assert!(accepted.is_empty());
+
+        // Tie of IPv4 addresses, but IPv6 is still accepted
+        let answers = vec![("s1", a), ("s2", b), ("s3", c)];
+        let (accepted, disagreed) = vote(&answers, 1);
+        assert_eq!(accepted, vec![c]);
+        assert!(disagreed.is_empty());
+
+        let answers = vec![("s1", a), ("s2", b), ("s3", a), ("s4", b)];
+        let (accepted, _) = vote(&answers, 2);
+        assert!(accepted.is_empty());
+
+        // All addresses are accepted if there is only one source or every source agrees
+        let answers = vec![("s1", a), ("s1", b)];
+        assert_eq!(vote(&answers, 1).0, vec![a, b]);
+        let answers = vec![("s1", a), ("s1", b), ("s2", b), ("s2", a)];
+        assert_eq!(vote(&answers, 2).0, vec![a, b]);
+        assert!(vote(&answers, 3).0.is_empty());
+
+        // A source votes once for an address it reports repeatedly
+        let answers = vec![("s1", a), ("s1", a), ("s2", b)];
+        let (accepted, _) = vote(&answers, 1);
+        assert!(accepted.is_empty());
+    }
+
+    #[test]
+    fn test_address_family_of_sources() {
+        async_std::task::block_on(async {
+            let mut sources = vec![];
+            let mut servers = vec![];
+            for (body, local_address) in [
+                ("203.0.113.5", Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))),
+                // IPv6 address from a source bound to IPv4 is dropped
+                ("2001:db8::6", Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))),
+                ("2001:db8::5", None),
+            ] {
+                let (address, server) =
+                    test_util::http_stand_in(vec![(200, String::from(body))]).await;
+                sources.push(UrlSource {
+                    url: format!("http://{}/", address),
+                    local_address,
+                });
+                servers.push(server);
+            }
+
+            let mut detector = GetIpByUrlDetector {
+                sources,
+                quorum: 1,
+                ..Default::default()
+            };
+            let options = test_util::default_options(Duration::from_secs(3));
+            let mut records = detector
+                .pull_request_content(options)
+                .await
+                .unwrap()
+                .to_vec();
+            records.sort();
+            assert_eq!(
+                records,
+                vec![
+                    Record::A(Ipv4Addr::new(203, 0, 113, 5)),
+                    Record::Aaaa("2001:db8::5".parse().unwrap()),
+                ]
+            );
+            for server in servers {
+                assert_eq!(server.await.len(), 1);
+            }
+        });
+    }
+
+    #[test]
+    fn test_multiple_addresses_of_one_source() {
+        async_std::task::block_on(async {
+            let (address, server) = test_util::http_stand_in(vec![(
+                200,
+                String::from(r#"{"ips":["192.0.2.1","192.0.2.2"]}"#),
+            )])
+            .await;
+            let url = format!("http://{}/", address);
+            let matches = crate::create_app().get_matches_from([
+                "ddns-cli",
+                "--get-ip-by-url",
+                url.as_str(),
+                "--get-ip-by-url-json-pointer",
+                "/ips",
+            ]);
+            let mut options = test_util::default_options(Duration::from_secs(3));
+            let mut detector = GetIpByUrlDetector::default();
+            detector.parse_options(&matches, &mut options);
+            assert_eq!(
+                detector.pull_request_content(options).await.unwrap(),
+                &[
+                    Record::A(Ipv4Addr::new(192, 0, 2, 1)),
+                    Record::A(Ipv4Addr::new(192, 0, 2, 2)),
+                ]
+            );
+            assert_eq!(server.await.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_request_options() {
+        async_std::task::block_on(async {
+            let (address, server) = test_util::http_stand_in(vec![
+                (200, String::from("203.0.113.5")),
+                (200, String::from("203.0.113.5")),
+            ])
+            .await;
+            let url = format!("http://{}/ip", address);
+
+            for args in [
+                vec![
+                    "--get-ip-by-url-method",
+                    "POST",
+                    "--get-ip-by-url-header",
+                    "X-Token: first",
+                    "--get-ip-by-url-header",
+                    "Accept: text/plain",
+                    "--get-ip-by-url-body",
+                    r#"{"query":"ip"}"#,
+                    "--get-ip-by-url-basic-auth",
+                    "user:pass",
+                ],
+                vec!["--get-ip-by-url-bearer", "secret-token"],
+            ] {
+                let mut command_line = vec!["ddns-cli", "--get-ip-by-url", url.as_str()];
+                command_line.extend(args);
+                let matches = crate::create_app().get_matches_from(command_line);
+                let mut options = test_util::default_options(Duration::from_secs(3));
+                let mut detector = GetIpByUrlDetector::default();
+                detector.parse_options(&matches, &mut options);
+                assert_eq!(
+                    detector.pull_request_content(options).await.unwrap(),
+                    &[Record::A(Ipv4Addr::new(203, 0, 113, 5))]
+                );
+            }
+
+            let requests = server.await;
+            assert!(requests[0].starts_with("POST /ip HTTP/1.1\r\n"));
+            assert!(requests[0].contains("x-token: first\r\n"));
+            assert!(requests[0].contains("accept: text/plain\r\n"));
+            // base64 of user:pass
+            assert!(requests[0].contains("authorization: Basic dXNlcjpwYXNz\r\n"));
+            assert!(requests[0].ends_with("\r\n\r\n{\"query\":\"ip\"}"));
+
+            assert!(requests[1].starts_with("GET /ip HTTP/1.1\r\n"));
+            assert!(requests[1].contains("authorization: Bearer secret-token\r\n"));
+        });
+    }
+}
@@ -0,0 +1,293 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use std::io;
+use std::net::IpAddr;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use regex::Regex;
+
+use super::super::option;
+use super::{Detector, DetectorResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+
+#[derive(Debug, Clone, PartialEq)]
+struct InterfaceAddress {
+    name: String,
+    addr: IpAddr,
+    temporary: bool,
+    deprecated: bool,
+}
+
+#[derive(Default)]
+pub struct InterfaceDetector {
+    names: Vec<String>,
+    name_regex: Option<Regex>,
+    ips: Vec<Record>,
+    logger: Option<slog::Logger>,
+}
+
+impl Detector for InterfaceDetector {
+    fn name(&self) -> &'static str {
+        "iface"
+    }
+
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("iface-name")
+                .long("iface-name")
+                .value_name("INTERFACE NAME")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Get ip address from network interfaces with these names"),
+        )
+        .arg(
+            Arg::new("iface-regex")
+                .long("iface-regex")
+                .value_name("REGEX")
+                .value_parser(|s: &str| Regex::new(s))
+                .help("Get ip address from network interfaces whose name match this regex"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let logger = options.create_logger("InterfaceDetector");
+        self.names = option::unwraper_multiple_values(matches, "iface-name", &logger, "interface");
+
+        // Invalid regex is rejected by value parser
+        self.name_regex = matches.get_one::<Regex>("iface-regex").cloned();
+
+        if !self.names.is_empty() || self.name_regex.is_some() {
+            self.logger = Some(logger);
+        }
+    }
+
+    fn run<'a, 'b>(&'a mut self, _: &mut SharedProgramOptions) -> BoxFuture<'b, DetectorResult<'a>>
+    where
+        'a: 'b,
+    {
+        let logger = match self.logger {
+            Some(ref l) => l.clone(),
+            None => return future::ready(Err(())).boxed(),
+        };
+
+        self.ips.clear();
+        let addresses = match list_interface_addresses() {
+            Ok(v) => v,
+            Err(e) => {
+                error!(logger, "List network interfaces failed, error: {}", e);
+                return future::ready(Err(())).boxed();
+            }
+        };
+
+        let selected: Vec<InterfaceAddress> = addresses
+            .into_iter()
+            .filter(|iface| self.select_interface(&iface.name))
+            .collect();
+
+        for iface in prefer_stable(selected) {
+            debug!(logger, "Add ip address {} of {}", iface.addr, iface.name);
+            self.ips.push(match iface.addr {
+                IpAddr::V4(ipv4) => Record::A(ipv4),
+                IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
+            });
+        }
+
+        if self.ips.is_empty() {
+            error!(logger, "No ip address found on selected network interfaces");
+            future::ready(Err(())).boxed()
+        } else {
+            future::ready(Ok(&self.ips)).boxed()
+        }
+    }
+}
+
+impl InterfaceDetector {
+    fn select_interface(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+            || self.name_regex.as_ref().is_some_and(|r| r.is_match(name))
+    }
+}
+
+fn ipv6_prefix_64(addr: &IpAddr) -> Option<u64> {
+    match addr {
+        IpAddr::V6(ipv6) => Some((u128::from(*ipv6) >> 64) as u64),
+        IpAddr::V4(_) => None,
+    }
+}
+
+/// Deprecated IPv6 addresses are removed, and temporary(privacy extension) IPv6 addresses are
+/// only used when there is no stable one in the same /64 prefix.
+fn prefer_stable(addresses: Vec<InterfaceAddress>) -> Vec<InterfaceAddress> {
+    let addresses: Vec<InterfaceAddress> =
+        addresses.into_iter().filter(|a| !a.deprecated).collect();
+    let stable_prefixes: Vec<u64> = addresses
+        .iter()
+        .filter(|a| !a.temporary)
+        .filter_map(|a| ipv6_prefix_64(&a.addr))
+        .collect();
+    addresses
+        .into_iter()
+        .filter(|a| {
+            !(a.temporary && ipv6_prefix_64(&a.addr).is_some_and(|p| stable_prefixes.contains(&p)))
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn list_interface_addresses() -> io::Result<Vec<InterfaceAddress>> {
+    use std::ffi::CStr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    let mut ret = vec![];
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills a linked list which is released by freeifaddrs below.
+    unsafe {
+        if libc::getifaddrs(&mut ifap) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut cursor = ifap;
+        while !cursor.is_null() {
+            let ifa = &*cursor;
+            cursor = ifa.ifa_next;
+            if ifa.ifa_addr.is_null() || ifa.ifa_name.is_null() {
+                continue;
+            }
+
+            let addr = match i32::from((*ifa.ifa_addr).sa_family) {
+                libc::AF_INET => {
+                    let sa = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(sa.sin_addr.s_addr)))
+                }
+                libc::AF_INET6 => {
+                    let sa = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                    IpAddr::V6(Ipv6Addr::from(sa.sin6_addr.s6_addr))
+                }
+                _ => continue,
+            };
+
+            ret.push(InterfaceAddress {
+                name: CStr::from_ptr(ifa.ifa_name).to_string_lossy().into_owned(),
+                addr,
+                temporary: false,
+                deprecated: false,
+            });
+        }
+
+        libc::freeifaddrs(ifap);
+    }
+
+    #[cfg(target_os = "linux")]
+    if let Ok(content) = std::fs::read_to_string("/proc/net/if_inet6") {
+        apply_ipv6_flags(&mut ret, &content);
+    }
+
+    Ok(ret)
+}
+
+#[cfg(not(unix))]
+fn list_interface_addresses() -> io::Result<Vec<InterfaceAddress>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "network interface detector is not supported on this platform",
+    ))
+}
+
+#[cfg(any(target_os = "linux", test))]
+const IFA_F_TEMPORARY: u32 = 0x01;
+#[cfg(any(target_os = "linux", test))]
+const IFA_F_DEPRECATED: u32 = 0x20;
+
+/// Read flags of IPv6 addresses from content of /proc/net/if_inet6, which is
+/// `<ADDRESS> <IFINDEX> <PREFIX LENGTH> <SCOPE> <FLAGS> <NAME>` for each line.
+#[cfg(any(target_os = "linux", test))]
+fn apply_ipv6_flags(addresses: &mut [InterfaceAddress], content: &str) {
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || fields[0].len() != 32 {
+            continue;
+        }
+
+        let addr = match u128::from_str_radix(fields[0], 16) {
+            Ok(v) => IpAddr::V6(std::net::Ipv6Addr::from(v)),
+            Err(_) => continue,
+        };
+        let flags = u32::from_str_radix(fields[4], 16).unwrap_or_default();
+        for iface in addresses
+            .iter_mut()
+            .filter(|a| a.addr == addr && a.name == fields[5])
+        {
+            iface.temporary = flags & IFA_F_TEMPORARY != 0;
+            iface.deprecated = flags & IFA_F_DEPRECATED != 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(name: &str, addr: &str) -> InterfaceAddress {
+        InterfaceAddress {
+            name: String::from(name),
+            addr: addr.parse().unwrap(),
+            temporary: false,
+            deprecated: false,
+        }
+    }
+
+    #[test]
+    fn test_select_interface() {
+        let mut detector = InterfaceDetector::default();
+        let app = detector.initialize(Command::new("test"));
+        let matches = app
+            .clone()
+            .try_get_matches_from(["test", "--iface-regex", "eth[0-9"]);
+        assert!(matches.is_err());
+        let matches = app
+            .try_get_matches_from([
+                "test",
+                "--iface-name",
+                "wg0",
+                "--iface-regex",
+                "^eth[0-9]+$",
+            ])
+            .unwrap();
+        detector.parse_options(
+            &matches,
+            &mut crate::test_util::default_options(std::time::Duration::from_secs(3)),
+        );
+        assert!(detector.select_interface("wg0"));
+        assert!(detector.select_interface("eth1"));
+        assert!(!detector.select_interface("veth1"));
+        assert!(detector.logger.is_some());
+    }
+
+    #[test]
+    fn test_prefer_stable_ipv6() {
+        let mut addresses = vec![
+            address("eth0", "192.168.1.2"),
+            address("eth0", "2001:db8::1234"),
+            address("eth0", "2001:db8::abcd:1"),
+            address("eth0", "2001:db8::abcd:2"),
+        ];
+        apply_ipv6_flags(
+            &mut addresses,
+            "20010db8000000000000000000001234 02 40 00 00     eth0\n\
+             20010db80000000000000000abcd0001 02 40 00 01     eth0\n\
+             20010db80000000000000000abcd0002 02 40 00 21     eth0\n",
+        );
+        assert!(addresses[2].temporary);
+        assert!(addresses[3].deprecated);
+
+        let selected = prefer_stable(addresses.clone());
+        assert_eq!(selected, vec![addresses[0].clone(), addresses[1].clone()]);
+
+        let link_local = address("eth0", "fe80::1");
+        let selected = prefer_stable(vec![link_local.clone(), addresses[2].clone()]);
+        assert_eq!(selected, vec![link_local, addresses[2].clone()]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::net::Ipv6Addr;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::option;
+use super::Record;
+
+/// Combine prefix of detected IPv6 addresses with interface identifiers of other hosts.
+#[derive(Default, Debug, Clone)]
+pub struct Ipv6Composer {
+    suffixes: Vec<Ipv6Addr>,
+    prefix_length: u8,
+    keep_original: bool,
+}
+
+fn compose(prefix: &Ipv6Addr, suffix: &Ipv6Addr, prefix_length: u8) -> Ipv6Addr {
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix_length))
+        .unwrap_or(0);
+    Ipv6Addr::from((u128::from(*prefix) & mask) | (u128::from(*suffix) & !mask))
+}
+
+impl Ipv6Composer {
+    pub fn initialize(app: Command) -> Command {
+        app.arg(
+            Arg::new("ipv6-suffix")
+                .long("ipv6-suffix")
+                .value_name("IPv6 SUFFIX")
+                .num_args(1..)
+                .value_parser(clap::value_parser!(Ipv6Addr))
+                .action(ArgAction::Append)
+                .help("Replace detected IPv6 addresses with prefix of them and these suffixes(::1234:5678 for example)"),
+        )
+        .arg(
+            Arg::new("ipv6-prefix-length")
+                .long("ipv6-prefix-length")
+                .value_name("LENGTH")
+                .default_value("64")
+                .value_parser(clap::value_parser!(u8).range(0..=128))
+                .help("Set prefix length of detected IPv6 addresses used by --ipv6-suffix"),
+        )
+        .arg(
+            Arg::new("ipv6-keep-original")
+                .long("ipv6-keep-original")
+                .action(ArgAction::SetTrue)
+                .help("Keep detected IPv6 addresses when --ipv6-suffix is set"),
+        )
+    }
+
+    pub fn parse_options(&mut self, matches: &ArgMatches, logger: &slog::Logger) {
+        self.suffixes =
+            option::unwraper_multiple_values(matches, "ipv6-suffix", logger, "IPv6 suffix");
+        self.prefix_length = option::unwraper_from_str_or(matches, "ipv6-prefix-length", 64);
+        self.keep_original = option::unwraper_flag(matches, "ipv6-keep-original");
+    }
+
+    /// Replace AAAA records with one record of every suffix for each detected prefix, other records
+    /// are always kept.
+    pub fn apply(&self, records: &mut Vec<Record>, logger: &slog::Logger) {
+        if self.suffixes.is_empty() {
+            return;
+        }
+
+        let mut composed = vec![];
+        for record in records.iter() {
+            if let Record::Aaaa(prefix) = record {
+                for suffix in &self.suffixes {
+                    let addr = compose(prefix, suffix, self.prefix_length);
+                    debug!(
+                        logger,
+                        "Compose ip address {} from {}/{} and {}",
+                        addr,
+                        prefix,
+                        self.prefix_length,
+                        suffix
+                    );
+                    let record = Record::Aaaa(addr);
+                    if !composed.contains(&record) {
+                        composed.push(record);
+                    }
+                }
+            }
+        }
+
+        if !self.keep_original {
+            records.retain(|r| !matches!(r, Record::Aaaa(_)));
+        }
+        records.extend(composed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_compose() {
+        let prefix: Ipv6Addr = "2001:db8:1:2:aaaa:bbbb:cccc:dddd".parse().unwrap();
+        let suffix: Ipv6Addr = "::1234:5678".parse().unwrap();
+        assert_eq!(
+            compose(&prefix, &suffix, 64),
+            "2001:db8:1:2::1234:5678".parse::<Ipv6Addr>().unwrap()
+        );
+        assert_eq!(
+            compose(&prefix, &"::ff:0:0:0:1".parse().unwrap(), 56),
+            "2001:db8:1:ff::1".parse::<Ipv6Addr>().unwrap()
+        );
+        assert_eq!(compose(&prefix, &suffix, 128), prefix);
+        assert_eq!(compose(&prefix, &suffix, 0), suffix);
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let app = Ipv6Composer::initialize(Command::new("test"));
+        let matches =
+            app.clone()
+                .try_get_matches_from(["test", "--ipv6-suffix", "::1", "::1:2:3:4:5:6:7:8"]);
+        assert!(matches.is_err());
+        let matches = app
+            .try_get_matches_from(["test", "--ipv6-suffix", "::1", "--ipv6-prefix-length", "56"])
+            .unwrap();
+        let mut composer = Ipv6Composer::default();
+        composer.parse_options(&matches, &slog::Logger::root(slog::Discard, o!()));
+        assert_eq!(composer.suffixes, vec![Ipv6Addr::LOCALHOST]);
+        assert_eq!(composer.prefix_length, 56);
+    }
+
+    #[test]
+    fn test_apply() {
+        let logger = slog::Logger::root(slog::Discard, o!());
+        let composer = Ipv6Composer {
+            suffixes: vec!["::1".parse().unwrap(), "::2".parse().unwrap()],
+            prefix_length: 64,
+            keep_original: false,
+        };
+        let mut records = vec![
+            Record::A(Ipv4Addr::new(192, 0, 2, 1)),
+            Record::Aaaa("2001:db8::abcd".parse().unwrap()),
+            Record::Aaaa("2001:db8::1234".parse().unwrap()),
+        ];
+        composer.apply(&mut records, &logger);
+        assert_eq!(
+            records,
+            vec![
+                Record::A(Ipv4Addr::new(192, 0, 2, 1)),
+                Record::Aaaa("2001:db8::1".parse().unwrap()),
+                Record::Aaaa("2001:db8::2".parse().unwrap()),
+            ]
+        );
+    }
+}
@@ -6,15 +6,34 @@ use futures::future::BoxFuture;
 extern crate clap;
 use crate::clap::{ArgMatches, Command};
 
+mod command_detector;
+mod dns_query_detector;
+mod extract;
+mod file_detector;
+mod filter;
 mod get_ip_by_url_detector;
+mod interface_detector;
+mod ipv6_composer;
 mod set_ip_detector;
+mod stun_detector;
+mod upnp_detector;
 
+pub type AddressExtractor = extract::AddressExtractor;
+pub type AddressFilter = filter::AddressFilter;
+pub type Ipv6Composer = ipv6_composer::Ipv6Composer;
 pub type SetIpDetector = set_ip_detector::SetIpDetector;
 pub type GetIpByUrlDetector = get_ip_by_url_detector::GetIpByUrlDetector;
+pub type InterfaceDetector = interface_detector::InterfaceDetector;
+pub type DnsQueryDetector = dns_query_detector::DnsQueryDetector;
+pub type StunDetector = stun_detector::StunDetector;
+pub type UpnpDetector = upnp_detector::UpnpDetector;
+pub type CommandDetector = command_detector::CommandDetector;
+pub type FileDetector = file_detector::FileDetector;
 pub type SharedProgramOptions = super::option::SharedProgramOptions;
 pub type HttpMethod = super::option::HttpMethod;
+pub type Trigger = super::daemon::Trigger;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Record {
     A(Ipv4Addr),
     Aaaa(Ipv6Addr),
@@ -38,12 +57,29 @@ impl fmt::Display for Record {
     }
 }
 
+impl Record {
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Record::A(_) => "A",
+            Record::Aaaa(_) => "AAAA",
+            Record::Cname(_) => "CNAME",
+            Record::Mx(_) => "MX",
+            Record::Txt(_) => "TXT",
+        }
+    }
+}
+
 pub type DetectorResult<'a> = Result<&'a Vec<Record>, ()>;
 
 pub trait Detector {
+    /// Name of detector, which is also the prefix of its address filter options.
+    fn name(&self) -> &'static str;
     fn initialize(&mut self, app: Command) -> Command;
     fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions);
 
+    /// Watch changes in daemon mode, and notify `trigger` to run detection at once.
+    fn watch(&mut self, _options: &SharedProgramOptions, _trigger: &Trigger) {}
+
     fn run<'a, 'b>(
         &'a mut self,
         options: &mut SharedProgramOptions,
@@ -0,0 +1,369 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use async_std::net::{ToSocketAddrs, UdpSocket};
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::daemon;
+use super::super::option;
+use super::{Detector, DetectorResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+
+const STUN_DEFAULT_PORT: u16 = 3478;
+const STUN_MAGIC_COOKIE: u32 = 0x2112_A442;
+const STUN_HEADER_LENGTH: usize = 20;
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+const STUN_BINDING_SUCCESS_RESPONSE: u16 = 0x0101;
+const STUN_BINDING_ERROR_RESPONSE: u16 = 0x0111;
+const STUN_ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const STUN_ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+/// XOR-MAPPED-ADDRESS used by some old servers implemented with draft of RFC 5389.
+const STUN_ATTR_XOR_MAPPED_ADDRESS_OLD: u16 = 0x8020;
+const STUN_RETRANSMIT_COUNT: u32 = 3;
+
+#[derive(Default)]
+pub struct StunDetector {
+    servers: Vec<String>,
+    ips: Vec<Record>,
+}
+
+fn generate_transaction_id() -> [u8; 12] {
+    let mut ret = [0_u8; 12];
+    ret[..8].copy_from_slice(&daemon::random_u64().to_be_bytes());
+    ret[8..].copy_from_slice(&(daemon::random_u64() as u32).to_be_bytes());
+    ret
+}
+
+/// Binding request without any attribute.
+fn build_binding_request(transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(STUN_HEADER_LENGTH);
+    ret.extend_from_slice(&STUN_BINDING_REQUEST.to_be_bytes());
+    ret.extend_from_slice(&0_u16.to_be_bytes());
+    ret.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+    ret.extend_from_slice(transaction_id);
+    ret
+}
+
+fn parse_address_attribute(
+    value: &[u8],
+    transaction_id: &[u8; 12],
+    xor: bool,
+) -> Result<IpAddr, String> {
+    if value.len() < 4 {
+        return Err(String::from("address attribute is too short"));
+    }
+
+    let mut key = [0_u8; 16];
+    if xor {
+        key[..4].copy_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+        key[4..].copy_from_slice(transaction_id);
+    }
+
+    match value[1] {
+        0x01 if value.len() >= 8 => {
+            let mut octets = [0_u8; 4];
+            for (i, o) in octets.iter_mut().enumerate() {
+                *o = value[4 + i] ^ key[i];
+            }
+            Ok(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        0x02 if value.len() >= 20 => {
+            let mut octets = [0_u8; 16];
+            for (i, o) in octets.iter_mut().enumerate() {
+                *o = value[4 + i] ^ key[i];
+            }
+            Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        family => Err(format!(
+            "invalid address family {} or length {}",
+            family,
+            value.len()
+        )),
+    }
+}
+
+/// Parse mapped address from binding response, XOR-MAPPED-ADDRESS is preferred and
+/// MAPPED-ADDRESS is used when it's not found.
+fn parse_binding_response(data: &[u8], transaction_id: &[u8; 12]) -> Result<IpAddr, String> {
+    if data.len() < STUN_HEADER_LENGTH {
+        return Err(String::from("message is too short"));
+    }
+
+    let message_type = u16::from_be_bytes([data[0], data[1]]);
+    let message_length = u16::from_be_bytes([data[2], data[3]]) as usize;
+    if data[4..8] != STUN_MAGIC_COOKIE.to_be_bytes() || data[8..20] != transaction_id[..] {
+        return Err(String::from("transaction id mismatch"));
+    }
+    if message_type == STUN_BINDING_ERROR_RESPONSE {
+        return Err(String::from("server responds an error"));
+    }
+    if message_type != STUN_BINDING_SUCCESS_RESPONSE {
+        return Err(format!("unexpected message type 0x{:04x}", message_type));
+    }
+    if data.len() < STUN_HEADER_LENGTH + message_length {
+        return Err(String::from("message is truncated"));
+    }
+
+    let mut mapped_address = None;
+    let mut attributes = &data[STUN_HEADER_LENGTH..STUN_HEADER_LENGTH + message_length];
+    while attributes.len() >= 4 {
+        let attr_type = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let attr_length = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        if attributes.len() < 4 + attr_length {
+            return Err(String::from("attribute is truncated"));
+        }
+
+        let value = &attributes[4..4 + attr_length];
+        match attr_type {
+            STUN_ATTR_XOR_MAPPED_ADDRESS | STUN_ATTR_XOR_MAPPED_ADDRESS_OLD => {
+                return parse_address_attribute(value, transaction_id, true);
+            }
+            STUN_ATTR_MAPPED_ADDRESS => {
+                mapped_address = Some(parse_address_attribute(value, transaction_id, false));
+            }
+            _ => {}
+        }
+
+        // attributes are padded to 4 bytes
+        let padded_length = (4 + attr_length + 3) & !3;
+        attributes = &attributes[padded_length.min(attributes.len())..];
+    }
+
+    mapped_address.unwrap_or_else(|| Err(String::from("no mapped address found")))
+}
+
+/// Send binding request to server and wait for response, the request is retransmitted on timeout.
+async fn query_mapped_address(server: SocketAddr, timeout: Duration) -> io::Result<IpAddr> {
+    let bind_address: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind_address).await?;
+    let transaction_id = generate_transaction_id();
+    let request = build_binding_request(&transaction_id);
+    let retransmit_timeout = timeout / STUN_RETRANSMIT_COUNT;
+
+    let mut buffer = [0_u8; 1024];
+    for _ in 0..STUN_RETRANSMIT_COUNT {
+        socket.send_to(&request, server).await?;
+
+        let deadline = Instant::now() + retransmit_timeout;
+        while let Some(left_time) = deadline.checked_duration_since(Instant::now()) {
+            let (size, from) =
+                match async_std::future::timeout(left_time, socket.recv_from(&mut buffer)).await {
+                    Ok(res) => res?,
+                    Err(_) => break,
+                };
+            // responses of other transactions are ignored
+            if from != server
+                || size < STUN_HEADER_LENGTH
+                || buffer[8..STUN_HEADER_LENGTH] != transaction_id
+            {
+                continue;
+            }
+
+            return parse_binding_response(&buffer[..size], &transaction_id)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "no response from STUN server",
+    ))
+}
+
+impl StunDetector {
+    async fn query_servers(&mut self, options: SharedProgramOptions) -> DetectorResult<'_> {
+        let logger = options.create_logger("StunDetector");
+        self.ips.clear();
+
+        let mut servers: Vec<(String, SocketAddr)> = vec![];
+        for server in &self.servers {
+            let (host, port) = option::split_host_port(server, STUN_DEFAULT_PORT);
+            match (host.as_str(), port).to_socket_addrs().await {
+                Ok(addrs) => servers.extend(addrs.map(|addr| (server.clone(), addr))),
+                Err(e) => {
+                    error!(
+                        logger,
+                        "Resolve STUN server {} failed, error: {}", server, e
+                    );
+                }
+            }
+        }
+
+        let results = future::join_all(servers.iter().map(|(_, addr)| {
+            debug!(logger, "Sending STUN binding request to {}", addr);
+            query_mapped_address(*addr, options.timeout)
+        }))
+        .await;
+
+        for ((server, addr), result) in servers.iter().zip(results) {
+            match result {
+                Ok(mapped_address) => {
+                    let mapped_address = mapped_address.to_canonical();
+                    debug!(
+                        logger,
+                        "Detect my address {} from STUN server {}({})",
+                        mapped_address,
+                        server,
+                        addr
+                    );
+                    let record = match mapped_address {
+                        IpAddr::V4(ipv4) => Record::A(ipv4),
+                        IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
+                    };
+                    if !self.ips.contains(&record) {
+                        self.ips.push(record);
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        logger,
+                        "Query STUN server {}({}) failed, error: {}", server, addr, e
+                    );
+                }
+            }
+        }
+
+        if self.ips.is_empty() {
+            Err(())
+        } else {
+            Ok(&self.ips)
+        }
+    }
+}
+
+impl Detector for StunDetector {
+    fn name(&self) -> &'static str {
+        "stun"
+    }
+
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("stun-server")
+                .long("stun-server")
+                .value_name("HOST:PORT")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Get ip by STUN binding request to these servers(stun.l.google.com:19302 for example), default port is 3478"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let logger = options.create_logger("StunDetector");
+        self.servers = option::unwraper_multiple_values(matches, "stun-server", &logger, "server");
+    }
+
+    fn run<'a, 'b>(
+        &'a mut self,
+        options: &mut SharedProgramOptions,
+    ) -> BoxFuture<'b, DetectorResult<'a>>
+    where
+        'a: 'b,
+    {
+        if self.servers.is_empty() {
+            future::ready(Err(())).boxed()
+        } else {
+            self.query_servers(options.clone()).boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_binding_response(request: &[u8], mapped_address: SocketAddr, xor: bool) -> Vec<u8> {
+        let mut key = [0_u8; 16];
+        if xor {
+            key.copy_from_slice(&request[4..20]);
+        }
+
+        let mut value = vec![0_u8];
+        value.push(if mapped_address.is_ipv4() { 0x01 } else { 0x02 });
+        value.extend_from_slice(
+            &(mapped_address.port() ^ u16::from_be_bytes([key[0], key[1]])).to_be_bytes(),
+        );
+        let octets = match mapped_address.ip() {
+            IpAddr::V4(ipv4) => ipv4.octets().to_vec(),
+            IpAddr::V6(ipv6) => ipv6.octets().to_vec(),
+        };
+        value.extend(octets.iter().zip(key.iter()).map(|(o, k)| o ^ k));
+
+        let attr_type = if xor {
+            STUN_ATTR_XOR_MAPPED_ADDRESS
+        } else {
+            STUN_ATTR_MAPPED_ADDRESS
+        };
+        // An unknown attribute with padding is put before the address
+        let mut attributes = vec![0x80, 0x22, 0x00, 0x03, b'f', b'o', b'o', 0x00];
+        attributes.extend_from_slice(&attr_type.to_be_bytes());
+        attributes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        attributes.extend(value);
+
+        let mut ret = STUN_BINDING_SUCCESS_RESPONSE.to_be_bytes().to_vec();
+        ret.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+        ret.extend_from_slice(&request[4..20]);
+        ret.extend(attributes);
+        ret
+    }
+
+    #[test]
+    fn test_parse_binding_response() {
+        let transaction_id = generate_transaction_id();
+        let request = build_binding_request(&transaction_id);
+        assert_eq!(request.len(), STUN_HEADER_LENGTH);
+
+        for (addr, xor) in [
+            ("203.0.113.7:40000", true),
+            ("[2001:db8::1234]:40000", true),
+            ("203.0.113.7:40000", false),
+        ] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            let response = build_binding_response(&request, addr, xor);
+            assert_eq!(
+                parse_binding_response(&response, &transaction_id),
+                Ok(addr.ip())
+            );
+        }
+
+        let response = build_binding_response(&request, "203.0.113.7:1".parse().unwrap(), true);
+        assert!(parse_binding_response(&response, &generate_transaction_id()).is_err());
+        assert!(
+            parse_binding_response(&response[..STUN_HEADER_LENGTH + 4], &transaction_id).is_err()
+        );
+    }
+
+    #[test]
+    fn test_query_local_server() {
+        async_std::task::block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let server_address = server.local_addr().unwrap();
+            let responder = async_std::task::spawn(async move {
+                let mut buffer = [0_u8; 1024];
+                let (size, from) = server.recv_from(&mut buffer).await.unwrap();
+                assert_eq!(size, STUN_HEADER_LENGTH);
+                let response = build_binding_response(
+                    &buffer[..size],
+                    "198.51.100.9:50000".parse().unwrap(),
+                    true,
+                );
+                server.send_to(&response, from).await.unwrap();
+            });
+
+            let addr = query_mapped_address(server_address, Duration::from_secs(3))
+                .await
+                .unwrap();
+            assert_eq!(addr, "198.51.100.9".parse::<IpAddr>().unwrap());
+            responder.await;
+        });
+    }
+}
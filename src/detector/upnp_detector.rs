@@ -0,0 +1,661 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use async_std::net::UdpSocket;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use regex::Regex;
+
+use super::super::daemon;
+use super::super::option;
+use super::{Detector, DetectorResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+type HttpMethod = super::HttpMethod;
+
+const SSDP_DEFAULT_ADDRESS: &str = "239.255.255.250:1900";
+const SSDP_SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+/// NAT-PMP and PCP share the same port.
+const NATPMP_PORT: u16 = 5351;
+const PCP_VERSION: u8 = 2;
+const PCP_OPCODE_MAP: u8 = 1;
+const PCP_MAP_LIFETIME: u32 = 120;
+const PCP_MAP_REQUEST_LENGTH: usize = 60;
+const UDP_RETRANSMIT_COUNT: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GatewayProtocol {
+    Igd,
+    NatPmp,
+    Pcp,
+}
+
+impl FromStr for GatewayProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "igd" => Ok(GatewayProtocol::Igd),
+            "natpmp" => Ok(GatewayProtocol::NatPmp),
+            "pcp" => Ok(GatewayProtocol::Pcp),
+            _ => Err(format!("unknown gateway protocol {}", s)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct UpnpDetector {
+    enabled: bool,
+    gateway: Option<Ipv4Addr>,
+    ssdp_address: String,
+    protocols: Vec<GatewayProtocol>,
+    ips: Vec<Record>,
+}
+
+/// Default gateway from content of /proc/net/route, which is
+/// `<IFACE> <DESTINATION> <GATEWAY> <FLAGS> ...` in hex of network byte order for each line.
+fn parse_default_gateway(content: &str) -> Option<Ipv4Addr> {
+    const RTF_GATEWAY: u32 = 0x02;
+    for line in content.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 || fields[1] != "00000000" {
+            continue;
+        }
+
+        let flags = u32::from_str_radix(fields[3], 16).unwrap_or_default();
+        if flags & RTF_GATEWAY == 0 {
+            continue;
+        }
+        if let Ok(gateway) = u32::from_str_radix(fields[2], 16) {
+            return Some(Ipv4Addr::from(gateway.to_ne_bytes()));
+        }
+    }
+    None
+}
+
+fn default_gateway() -> Option<Ipv4Addr> {
+    std::fs::read_to_string("/proc/net/route")
+        .ok()
+        .and_then(|content| parse_default_gateway(&content))
+}
+
+/// Text of the first element with this local name, namespace prefix is ignored.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let pattern = format!(
+        r"(?s)<(?:[\w-]+:)?{}(?:\s[^>]*)?>(.*?)</(?:[\w-]+:)?{}>",
+        tag, tag
+    );
+    Regex::new(&pattern)
+        .ok()?
+        .captures(xml)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().trim().to_string())
+}
+
+fn parse_ssdp_location(response: &str) -> Option<String> {
+    let mut lines = response.lines();
+    if !lines.next()?.contains(" 200 ") {
+        return None;
+    }
+
+    lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("location") {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Control URL and service type of WANIPConnection or WANPPPConnection service.
+fn parse_control_url(description: &str, location: &str) -> Result<(String, String), String> {
+    let base = xml_text(description, "URLBase").unwrap_or_else(|| location.to_string());
+    let base = reqwest::Url::parse(&base).map_err(|e| e.to_string())?;
+    let service_regex = Regex::new(r"(?s)<(?:[\w-]+:)?service>(.*?)</(?:[\w-]+:)?service>")
+        .map_err(|e| e.to_string())?;
+    for caps in service_regex.captures_iter(description) {
+        let service = &caps[1];
+        let service_type = match xml_text(service, "serviceType") {
+            Some(v) => v,
+            None => continue,
+        };
+        if !service_type.contains(":WANIPConnection:")
+            && !service_type.contains(":WANPPPConnection:")
+        {
+            continue;
+        }
+
+        if let Some(control_url) = xml_text(service, "controlURL") {
+            let control_url = base.join(&control_url).map_err(|e| e.to_string())?;
+            return Ok((control_url.to_string(), service_type));
+        }
+    }
+
+    Err(String::from("WANIPConnection service not found"))
+}
+
+async fn ssdp_discover(
+    ssdp_address: SocketAddr,
+    gateway: Option<Ipv4Addr>,
+    timeout: Duration,
+) -> io::Result<String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let request = format!(
+        "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+        ssdp_address, SSDP_SEARCH_TARGET
+    );
+    let retransmit_timeout = timeout / UDP_RETRANSMIT_COUNT;
+
+    let mut buffer = [0_u8; 2048];
+    for _ in 0..UDP_RETRANSMIT_COUNT {
+        socket.send_to(request.as_bytes(), ssdp_address).await?;
+
+        let deadline = Instant::now() + retransmit_timeout;
+        while let Some(left_time) = deadline.checked_duration_since(Instant::now()) {
+            let (size, from) =
+                match async_std::future::timeout(left_time, socket.recv_from(&mut buffer)).await {
+                    Ok(res) => res?,
+                    Err(_) => break,
+                };
+            // Responses of other devices are ignored when gateway is set
+            if gateway.is_some_and(|g| from.ip() != IpAddr::V4(g)) {
+                continue;
+            }
+            if let Some(location) = parse_ssdp_location(&String::from_utf8_lossy(&buffer[..size])) {
+                return Ok(location);
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "no internet gateway device found",
+    ))
+}
+
+async fn igd_external_address(
+    options: &SharedProgramOptions,
+    ssdp_address: SocketAddr,
+    gateway: Option<Ipv4Addr>,
+) -> Result<IpAddr, String> {
+    let location = ssdp_discover(ssdp_address, gateway, options.timeout)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let description = options
+        .http(HttpMethod::Get, &location)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    let (control_url, service_type) = parse_control_url(&description, &location)?;
+
+    let body = format!(
+        "<?xml version=\"1.0\"?>\r\n\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+         <s:Body><u:GetExternalIPAddress xmlns:u=\"{}\"></u:GetExternalIPAddress></s:Body>\
+         </s:Envelope>\r\n",
+        service_type
+    );
+    let response = options
+        .http(HttpMethod::Post, &control_url)
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .header(
+            "SOAPAction",
+            format!("\"{}#GetExternalIPAddress\"", service_type),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let response = response.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!(
+            "GetExternalIPAddress failed, status: {}, error: {}",
+            status,
+            xml_text(&response, "errorDescription").unwrap_or_default()
+        ));
+    }
+
+    let addr = xml_text(&response, "NewExternalIPAddress")
+        .ok_or_else(|| String::from("NewExternalIPAddress not found"))?;
+    IpAddr::from_str(&addr).map_err(|e| format!("invalid address {}, {}", addr, e))
+}
+
+/// Send request by connected socket and wait for the accepted response, the request is
+/// retransmitted on timeout.
+async fn udp_exchange<F>(
+    socket: &UdpSocket,
+    request: &[u8],
+    timeout: Duration,
+    accept: F,
+) -> io::Result<Vec<u8>>
+where
+    F: Fn(&[u8]) -> bool,
+{
+    let retransmit_timeout = timeout / UDP_RETRANSMIT_COUNT;
+    let mut buffer = [0_u8; 1100];
+    for _ in 0..UDP_RETRANSMIT_COUNT {
+        socket.send(request).await?;
+
+        let deadline = Instant::now() + retransmit_timeout;
+        while let Some(left_time) = deadline.checked_duration_since(Instant::now()) {
+            let size = match async_std::future::timeout(left_time, socket.recv(&mut buffer)).await {
+                Ok(res) => res?,
+                Err(_) => break,
+            };
+            if accept(&buffer[..size]) {
+                return Ok(buffer[..size].to_vec());
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "no response from gateway",
+    ))
+}
+
+fn parse_natpmp_response(data: &[u8]) -> Result<IpAddr, String> {
+    if data.len() < 12 || data[0] != 0 || data[1] != 128 {
+        return Err(String::from("invalid NAT-PMP response"));
+    }
+
+    let result = u16::from_be_bytes([data[2], data[3]]);
+    if result != 0 {
+        return Err(format!("NAT-PMP result code {}", result));
+    }
+    Ok(IpAddr::V4(Ipv4Addr::new(
+        data[8], data[9], data[10], data[11],
+    )))
+}
+
+async fn natpmp_external_address(gateway: SocketAddr, timeout: Duration) -> Result<IpAddr, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| e.to_string())?;
+    socket.connect(gateway).await.map_err(|e| e.to_string())?;
+
+    let response = udp_exchange(&socket, &[0, 0], timeout, |data| {
+        data.len() >= 2 && data[0] == 0 && data[1] == 128
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    parse_natpmp_response(&response)
+}
+
+fn ipv6_mapped_octets(addr: IpAddr) -> [u8; 16] {
+    match addr {
+        IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped().octets(),
+        IpAddr::V6(ipv6) => ipv6.octets(),
+    }
+}
+
+/// MAP request of UDP from the client address and port.
+fn build_pcp_map_request(client: SocketAddr, nonce: &[u8; 12], lifetime: u32) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(PCP_MAP_REQUEST_LENGTH);
+    ret.extend_from_slice(&[PCP_VERSION, PCP_OPCODE_MAP, 0, 0]);
+    ret.extend_from_slice(&lifetime.to_be_bytes());
+    ret.extend_from_slice(&ipv6_mapped_octets(client.ip()));
+    ret.extend_from_slice(nonce);
+    ret.extend_from_slice(&[17, 0, 0, 0]);
+    ret.extend_from_slice(&client.port().to_be_bytes());
+    // Suggested external port and address are not set
+    ret.extend_from_slice(&[0_u8; 18]);
+    ret
+}
+
+fn is_pcp_map_response(data: &[u8], nonce: &[u8; 12]) -> bool {
+    data.len() >= PCP_MAP_REQUEST_LENGTH
+        && data[0] == PCP_VERSION
+        && data[1] == (0x80 | PCP_OPCODE_MAP)
+        && data[24..36] == nonce[..]
+}
+
+fn parse_pcp_map_response(data: &[u8]) -> Result<IpAddr, String> {
+    if data[3] != 0 {
+        return Err(format!("PCP result code {}", data[3]));
+    }
+
+    let octets: [u8; 16] = data[44..60]
+        .try_into()
+        .map_err(|_| "invalid PCP response")?;
+    Ok(IpAddr::V6(Ipv6Addr::from(octets)).to_canonical())
+}
+
+/// Create a short-lived mapping to learn the external address, and delete it at once.
+async fn pcp_external_address(gateway: SocketAddr, timeout: Duration) -> Result<IpAddr, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .await
+        .map_err(|e| e.to_string())?;
+    socket.connect(gateway).await.map_err(|e| e.to_string())?;
+    let client = socket.local_addr().map_err(|e| e.to_string())?;
+
+    let mut nonce = [0_u8; 12];
+    nonce[..8].copy_from_slice(&daemon::random_u64().to_be_bytes());
+    nonce[8..].copy_from_slice(&(daemon::random_u64() as u32).to_be_bytes());
+
+    let request = build_pcp_map_request(client, &nonce, PCP_MAP_LIFETIME);
+    let response = udp_exchange(&socket, &request, timeout, |data| {
+        is_pcp_map_response(data, &nonce)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    let ret = parse_pcp_map_response(&response);
+
+    if ret.is_ok() {
+        let request = build_pcp_map_request(client, &nonce, 0);
+        let _ = udp_exchange(&socket, &request, timeout, |data| {
+            is_pcp_map_response(data, &nonce)
+        })
+        .await;
+    }
+    ret
+}
+
+impl UpnpDetector {
+    async fn query_gateway(&mut self, options: SharedProgramOptions) -> DetectorResult<'_> {
+        let logger = options.create_logger("UpnpDetector");
+        self.ips.clear();
+
+        let gateway = self.gateway.or_else(default_gateway);
+        let ssdp_address = match SocketAddr::from_str(&self.ssdp_address) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    logger,
+                    "Invalid SSDP address {}, error: {}", self.ssdp_address, e
+                );
+                return Err(());
+            }
+        };
+
+        for protocol in &self.protocols {
+            debug!(logger, "Get external address by {:?}", protocol);
+            let result = match protocol {
+                GatewayProtocol::Igd => igd_external_address(&options, ssdp_address, gateway).await,
+                GatewayProtocol::NatPmp | GatewayProtocol::Pcp => match gateway {
+                    Some(gateway) => {
+                        let gateway = SocketAddr::from((gateway, NATPMP_PORT));
+                        if *protocol == GatewayProtocol::NatPmp {
+                            natpmp_external_address(gateway, options.timeout).await
+                        } else {
+                            pcp_external_address(gateway, options.timeout).await
+                        }
+                    }
+                    None => Err(String::from("default gateway not found")),
+                },
+            };
+
+            match result {
+                Ok(addr) => {
+                    debug!(logger, "Detect my address {} by {:?}", addr, protocol);
+                    self.ips.push(match addr {
+                        IpAddr::V4(ipv4) => Record::A(ipv4),
+                        IpAddr::V6(ipv6) => Record::Aaaa(ipv6),
+                    });
+                    return Ok(&self.ips);
+                }
+                Err(e) => {
+                    error!(
+                        logger,
+                        "Get external address by {:?} failed, error: {}", protocol, e
+                    );
+                }
+            }
+        }
+
+        Err(())
+    }
+}
+
+impl Detector for UpnpDetector {
+    fn name(&self) -> &'static str {
+        "upnp"
+    }
+
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("upnp")
+                .long("upnp")
+                .action(ArgAction::SetTrue)
+                .help("Get ip from gateway by UPnP IGD, NAT-PMP or PCP"),
+        )
+        .arg(
+            Arg::new("upnp-gateway")
+                .long("upnp-gateway")
+                .value_name("IPv4 ADDRESS")
+                .help("Set gateway address, default gateway is used if it's not set"),
+        )
+        .arg(
+            Arg::new("upnp-ssdp-address")
+                .long("upnp-ssdp-address")
+                .value_name("IP:PORT")
+                .default_value(SSDP_DEFAULT_ADDRESS)
+                .help("Set address to send SSDP search request"),
+        )
+        .arg(
+            Arg::new("upnp-protocol")
+                .long("upnp-protocol")
+                .value_name("PROTOCOL")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .default_values(["igd", "natpmp", "pcp"])
+                .ignore_case(true)
+                .value_parser(["igd", "natpmp", "pcp"])
+                .help("Set protocols to get ip from gateway, they are tried in order"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let logger = options.create_logger("UpnpDetector");
+        self.enabled = option::unwraper_flag(matches, "upnp");
+        self.gateway =
+            option::unwraper_multiple_values(matches, "upnp-gateway", &logger, "gateway")
+                .first()
+                .cloned();
+        self.ssdp_address = option::unwraper_option_or(
+            matches,
+            "upnp-ssdp-address",
+            String::from(SSDP_DEFAULT_ADDRESS),
+        );
+        self.protocols =
+            option::unwraper_multiple_values(matches, "upnp-protocol", &logger, "protocol");
+    }
+
+    fn run<'a, 'b>(
+        &'a mut self,
+        options: &mut SharedProgramOptions,
+    ) -> BoxFuture<'b, DetectorResult<'a>>
+    where
+        'a: 'b,
+    {
+        if !self.enabled {
+            future::ready(Err(())).boxed()
+        } else {
+            self.query_gateway(options.clone()).boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use async_std::io::{ReadExt, WriteExt};
+    use async_std::net::TcpListener;
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <deviceType>urn:schemas-upnp-org:device:InternetGatewayDevice:1</deviceType>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>
+        <controlURL>/ctl/L3F</controlURL>
+      </service>
+    </serviceList>
+    <deviceList><device><deviceList><device>
+      <serviceList>
+        <service>
+          <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+          <controlURL>/ctl/IPConn</controlURL>
+        </service>
+      </serviceList>
+    </device></deviceList></device></deviceList>
+  </device>
+</root>"#;
+
+    const SOAP_RESPONSE: &str = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/"><s:Body>
+<u:GetExternalIPAddressResponse xmlns:u="urn:schemas-upnp-org:service:WANIPConnection:1">
+<NewExternalIPAddress>203.0.113.20</NewExternalIPAddress>
+</u:GetExternalIPAddressResponse></s:Body></s:Envelope>"#;
+
+    /// Read one HTTP request and return it with the body.
+    async fn read_http_request(stream: &mut async_std::net::TcpStream) -> String {
+        let mut data = vec![];
+        let mut buffer = [0_u8; 1024];
+        loop {
+            let size = stream.read(&mut buffer).await.unwrap();
+            data.extend_from_slice(&buffer[..size]);
+            let text = String::from_utf8_lossy(&data).to_string();
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|l| {
+                        let (name, value) = l.split_once(':')?;
+                        if name.eq_ignore_ascii_case("content-length") {
+                            value.trim().parse::<usize>().ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .unwrap_or(0);
+                if data.len() >= header_end + 4 + content_length {
+                    return text;
+                }
+            }
+            if size == 0 {
+                return text;
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_default_gateway() {
+        let content = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                       eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+                       eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\n";
+        let gateway = u32::from_str_radix("0101A8C0", 16).unwrap().to_ne_bytes();
+        assert_eq!(
+            parse_default_gateway(content),
+            Some(Ipv4Addr::from(gateway))
+        );
+        assert_eq!(parse_default_gateway("Iface\tDestination\n"), None);
+    }
+
+    #[test]
+    fn test_igd() {
+        async_std::task::block_on(async {
+            let ssdp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let ssdp_address = ssdp.local_addr().unwrap();
+            let http = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let http_address = http.local_addr().unwrap();
+
+            let gateway = async_std::task::spawn(async move {
+                let mut buffer = [0_u8; 1024];
+                let (size, from) = ssdp.recv_from(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..size]).to_string();
+                assert!(request.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+                assert!(request.contains(SSDP_SEARCH_TARGET));
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nST: {}\r\nLocation: http://{}/rootDesc.xml\r\n\r\n",
+                    SSDP_SEARCH_TARGET, http_address
+                );
+                ssdp.send_to(response.as_bytes(), from).await.unwrap();
+
+                let mut requests = vec![];
+                for body in [DESCRIPTION, SOAP_RESPONSE] {
+                    let (mut stream, _) = http.accept().await.unwrap();
+                    requests.push(read_http_request(&mut stream).await);
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+                requests
+            });
+
+            let options = test_util::default_options(Duration::from_secs(3));
+            let addr = igd_external_address(&options, ssdp_address, None)
+                .await
+                .unwrap();
+            assert_eq!(addr, "203.0.113.20".parse::<IpAddr>().unwrap());
+
+            let requests = gateway.await;
+            assert!(requests[0].starts_with("GET /rootDesc.xml HTTP/1.1\r\n"));
+            assert!(requests[1].starts_with("POST /ctl/IPConn HTTP/1.1\r\n"));
+            assert!(requests[1].to_ascii_lowercase().contains(
+                "soapaction: \"urn:schemas-upnp-org:service:wanipconnection:1#getexternalipaddress\""
+            ));
+        });
+    }
+
+    #[test]
+    fn test_natpmp_and_pcp() {
+        async_std::task::block_on(async {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let server_address = server.local_addr().unwrap();
+
+            let gateway = async_std::task::spawn(async move {
+                let mut lifetimes = vec![];
+                let mut buffer = [0_u8; 1100];
+                for _ in 0..3 {
+                    let (size, from) = server.recv_from(&mut buffer).await.unwrap();
+                    let request = &buffer[..size];
+                    let response = if request == [0, 0] {
+                        vec![0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 21]
+                    } else {
+                        assert_eq!(size, PCP_MAP_REQUEST_LENGTH);
+                        assert_eq!(request[..2], [PCP_VERSION, PCP_OPCODE_MAP]);
+                        lifetimes.push(u32::from_be_bytes(request[4..8].try_into().unwrap()));
+                        let mut response = request.to_vec();
+                        response[1] |= 0x80;
+                        response[8..24].fill(0);
+                        response[44..60].copy_from_slice(
+                            &Ipv4Addr::new(203, 0, 113, 22).to_ipv6_mapped().octets(),
+                        );
+                        response
+                    };
+                    server.send_to(&response, from).await.unwrap();
+                }
+                lifetimes
+            });
+
+            let timeout = Duration::from_secs(3);
+            assert_eq!(
+                natpmp_external_address(server_address, timeout).await,
+                Ok("203.0.113.21".parse().unwrap())
+            );
+            assert_eq!(
+                pcp_external_address(server_address, timeout).await,
+                Ok("203.0.113.22".parse().unwrap())
+            );
+            assert_eq!(gateway.await, vec![PCP_MAP_LIFETIME, 0]);
+        });
+    }
+}
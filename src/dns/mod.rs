@@ -0,0 +1,527 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{TcpStream, UdpSocket};
+
+mod tsig;
+
+pub type TsigAlgorithm = tsig::TsigAlgorithm;
+pub type TsigKey = tsig::TsigKey;
+pub use tsig::response_error as tsig_error;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_TSIG: u16 = 250;
+
+pub const CLASS_IN: u16 = 1;
+pub const CLASS_CH: u16 = 3;
+pub const CLASS_ANY: u16 = 255;
+
+pub const FLAG_RD: u16 = 0x0100;
+pub const FLAG_TC: u16 = 0x0200;
+pub const FLAG_QR: u16 = 0x8000;
+pub const OPCODE_UPDATE: u16 = 5 << 11;
+
+const HEADER_LENGTH: usize = 12;
+const MAX_COMPRESSION_POINTERS: usize = 32;
+const UDP_RETRANSMIT_COUNT: u32 = 3;
+
+pub fn parse_type(name: &str) -> Result<u16, String> {
+    match name.to_ascii_uppercase().as_str() {
+        "A" => Ok(TYPE_A),
+        "TXT" => Ok(TYPE_TXT),
+        "AAAA" => Ok(TYPE_AAAA),
+        _ => Err(format!("unsupported DNS type {}", name)),
+    }
+}
+
+/// Name of rcode, including extended rcodes of TSIG.
+pub fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => String::from("NOERROR"),
+        1 => String::from("FORMERR"),
+        2 => String::from("SERVFAIL"),
+        3 => String::from("NXDOMAIN"),
+        4 => String::from("NOTIMP"),
+        5 => String::from("REFUSED"),
+        6 => String::from("YXDOMAIN"),
+        7 => String::from("YXRRSET"),
+        8 => String::from("NXRRSET"),
+        9 => String::from("NOTAUTH"),
+        10 => String::from("NOTZONE"),
+        16 => String::from("BADSIG"),
+        17 => String::from("BADKEY"),
+        18 => String::from("BADTIME"),
+        _ => format!("RCODE{}", rcode),
+    }
+}
+
+pub fn parse_class(name: &str) -> Result<u16, String> {
+    match name.to_ascii_uppercase().as_str() {
+        "IN" => Ok(CLASS_IN),
+        "CH" => Ok(CLASS_CH),
+        _ => Err(format!("unsupported DNS class {}", name)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceRecord {
+    pub name: String,
+    pub rtype: u16,
+    pub rclass: u16,
+    pub ttl: u32,
+    pub data: Vec<u8>,
+}
+
+impl ResourceRecord {
+    /// Address of A or AAAA record.
+    pub fn address(&self) -> Option<IpAddr> {
+        match self.rtype {
+            TYPE_A if self.data.len() == 4 => {
+                let octets: [u8; 4] = self.data[..].try_into().ok()?;
+                Some(IpAddr::V4(Ipv4Addr::from(octets)))
+            }
+            TYPE_AAAA if self.data.len() == 16 => {
+                let octets: [u8; 16] = self.data[..].try_into().ok()?;
+                Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Character strings of TXT record.
+    pub fn txt(&self) -> Option<Vec<String>> {
+        if self.rtype != TYPE_TXT {
+            return None;
+        }
+
+        let mut ret = vec![];
+        let mut data = &self.data[..];
+        while let Some((length, rest)) = data.split_first() {
+            let length = *length as usize;
+            if rest.len() < length {
+                return None;
+            }
+            ret.push(String::from_utf8_lossy(&rest[..length]).into_owned());
+            data = &rest[length..];
+        }
+        Some(ret)
+    }
+}
+
+/// DNS message, names are always encoded without compression.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+}
+
+pub fn encode_name(name: &str, output: &mut Vec<u8>) -> Result<(), String> {
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() {
+            if name.trim_end_matches('.').is_empty() {
+                break;
+            }
+            return Err(format!("empty label in {}", name));
+        }
+        if label.len() > 63 {
+            return Err(format!("label {} is too long", label));
+        }
+        output.push(label.len() as u8);
+        output.extend_from_slice(label.as_bytes());
+    }
+    output.push(0);
+    Ok(())
+}
+
+fn decode_name(data: &[u8], mut offset: usize) -> Result<(String, usize), String> {
+    let mut labels: Vec<String> = vec![];
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let length = *data.get(offset).ok_or("name is truncated")? as usize;
+        match length & 0xc0 {
+            0x00 => {
+                if length == 0 {
+                    return Ok((labels.join("."), end.unwrap_or(offset + 1)));
+                }
+                let label = data
+                    .get(offset + 1..offset + 1 + length)
+                    .ok_or("label is truncated")?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + length;
+            }
+            0xc0 => {
+                let low = *data.get(offset + 1).ok_or("pointer is truncated")? as usize;
+                jumps += 1;
+                if jumps > MAX_COMPRESSION_POINTERS {
+                    return Err(String::from("too many compression pointers"));
+                }
+                end.get_or_insert(offset + 2);
+                offset = ((length & 0x3f) << 8) | low;
+            }
+            _ => return Err(format!("invalid label type 0x{:02x}", length)),
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|v| u16::from_be_bytes([v[0], v[1]]))
+        .ok_or_else(|| String::from("message is truncated"))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|v| u32::from_be_bytes([v[0], v[1], v[2], v[3]]))
+        .ok_or_else(|| String::from("message is truncated"))
+}
+
+fn decode_records(
+    data: &[u8],
+    offset: &mut usize,
+    count: u16,
+) -> Result<Vec<ResourceRecord>, String> {
+    let mut ret = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (name, next) = decode_name(data, *offset)?;
+        let rtype = read_u16(data, next)?;
+        let rclass = read_u16(data, next + 2)?;
+        let ttl = read_u32(data, next + 4)?;
+        let length = read_u16(data, next + 8)? as usize;
+        let rdata = data
+            .get(next + 10..next + 10 + length)
+            .ok_or("record data is truncated")?;
+        ret.push(ResourceRecord {
+            name,
+            rtype,
+            rclass,
+            ttl,
+            data: rdata.to_vec(),
+        });
+        *offset = next + 10 + length;
+    }
+    Ok(ret)
+}
+
+impl Message {
+    /// Recursive query of one question.
+    pub fn query(id: u16, name: &str, qtype: u16, qclass: u16) -> Self {
+        Message {
+            id,
+            flags: FLAG_RD,
+            questions: vec![Question {
+                name: name.to_string(),
+                qtype,
+                qclass,
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Dynamic update of RFC 2136, records to delete and add are put in authorities.
+    pub fn update(id: u16, zone: &str) -> Self {
+        Message {
+            id,
+            flags: OPCODE_UPDATE,
+            questions: vec![Question {
+                name: zone.to_string(),
+                qtype: TYPE_SOA,
+                qclass: CLASS_IN,
+            }],
+            ..Default::default()
+        }
+    }
+
+    pub fn rcode(&self) -> u8 {
+        (self.flags & 0x000f) as u8
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut ret = Vec::with_capacity(512);
+        ret.extend_from_slice(&self.id.to_be_bytes());
+        ret.extend_from_slice(&self.flags.to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            ret.extend_from_slice(&(count as u16).to_be_bytes());
+        }
+
+        for question in &self.questions {
+            encode_name(&question.name, &mut ret)?;
+            ret.extend_from_slice(&question.qtype.to_be_bytes());
+            ret.extend_from_slice(&question.qclass.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(self.authorities.iter())
+            .chain(self.additionals.iter())
+        {
+            encode_name(&record.name, &mut ret)?;
+            ret.extend_from_slice(&record.rtype.to_be_bytes());
+            ret.extend_from_slice(&record.rclass.to_be_bytes());
+            ret.extend_from_slice(&record.ttl.to_be_bytes());
+            if record.data.len() > u16::MAX as usize {
+                return Err(format!("data of {} is too long", record.name));
+            }
+            ret.extend_from_slice(&(record.data.len() as u16).to_be_bytes());
+            ret.extend_from_slice(&record.data);
+        }
+        Ok(ret)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        if data.len() < HEADER_LENGTH {
+            return Err(String::from("message is too short"));
+        }
+
+        let mut ret = Message {
+            id: read_u16(data, 0)?,
+            flags: read_u16(data, 2)?,
+            ..Default::default()
+        };
+        let question_count = read_u16(data, 4)?;
+        let answer_count = read_u16(data, 6)?;
+        let authority_count = read_u16(data, 8)?;
+        let additional_count = read_u16(data, 10)?;
+
+        let mut offset = HEADER_LENGTH;
+        for _ in 0..question_count {
+            let (name, next) = decode_name(data, offset)?;
+            ret.questions.push(Question {
+                name,
+                qtype: read_u16(data, next)?,
+                qclass: read_u16(data, next + 2)?,
+            });
+            offset = next + 4;
+        }
+        ret.answers = decode_records(data, &mut offset, answer_count)?;
+        ret.authorities = decode_records(data, &mut offset, authority_count)?;
+        ret.additionals = decode_records(data, &mut offset, additional_count)?;
+        Ok(ret)
+    }
+}
+
+fn invalid_data(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Send request by UDP, and retry by TCP if the response is truncated.
+pub async fn exchange(
+    server: SocketAddr,
+    request: &[u8],
+    timeout: Duration,
+) -> io::Result<Message> {
+    let response = exchange_data(server, request, timeout, false).await?;
+    Message::decode(&response).map_err(invalid_data)
+}
+
+/// Raw response which is required to verify TSIG, request is only sent by TCP if `tcp` is set.
+pub async fn exchange_data(
+    server: SocketAddr,
+    request: &[u8],
+    timeout: Duration,
+    tcp: bool,
+) -> io::Result<Vec<u8>> {
+    if !tcp {
+        let response = exchange_udp(server, request, timeout).await?;
+        if read_u16(&response, 2).map_err(invalid_data)? & FLAG_TC == 0 {
+            return Ok(response);
+        }
+    }
+    exchange_tcp(server, request, timeout).await
+}
+
+async fn exchange_udp(
+    server: SocketAddr,
+    request: &[u8],
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    let bind_address: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind_address).await?;
+    let id = read_u16(request, 0).map_err(invalid_data)?;
+    let retransmit_timeout = timeout / UDP_RETRANSMIT_COUNT;
+
+    let mut buffer = vec![0_u8; 65535];
+    for _ in 0..UDP_RETRANSMIT_COUNT {
+        socket.send_to(request, server).await?;
+
+        let deadline = Instant::now() + retransmit_timeout;
+        while let Some(left_time) = deadline.checked_duration_since(Instant::now()) {
+            let (size, from) =
+                match async_std::future::timeout(left_time, socket.recv_from(&mut buffer)).await {
+                    Ok(res) => res?,
+                    Err(_) => break,
+                };
+            // responses of other requests are ignored
+            if from != server
+                || read_u16(&buffer[..size], 0) != Ok(id)
+                || read_u16(&buffer[..size], 2).unwrap_or_default() & FLAG_QR == 0
+            {
+                continue;
+            }
+
+            return Ok(buffer[..size].to_vec());
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "no response from DNS server",
+    ))
+}
+
+async fn exchange_tcp(
+    server: SocketAddr,
+    request: &[u8],
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    async_std::io::timeout(timeout, async {
+        let mut stream = TcpStream::connect(server).await?;
+        let mut data = Vec::with_capacity(request.len() + 2);
+        data.extend_from_slice(&(request.len() as u16).to_be_bytes());
+        data.extend_from_slice(request);
+        stream.write_all(&data).await?;
+
+        let mut length = [0_u8; 2];
+        stream.read_exact(&mut length).await?;
+        let mut buffer = vec![0_u8; u16::from_be_bytes(length) as usize];
+        stream.read_exact(&mut buffer).await?;
+        Ok(buffer)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_query() {
+        let request = Message::query(0x1234, "myip.opendns.com.", TYPE_A, CLASS_IN);
+        let data = request.encode().unwrap();
+        assert_eq!(
+            data,
+            b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+              \x04myip\x07opendns\x03com\x00\x00\x01\x00\x01"
+        );
+        assert_eq!(
+            Message::decode(&data).unwrap().questions[0].name,
+            "myip.opendns.com"
+        );
+        assert!(encode_name("a..b", &mut vec![]).is_err());
+        assert!(encode_name(&"a".repeat(64), &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn test_decode_compressed_response() {
+        // Response of whoami.cloudflare TXT CH, and an A record pointing to the question name
+        let mut data = b"\xab\xcd\x81\x80\x00\x01\x00\x02\x00\x00\x00\x00\
+              \x06whoami\x0acloudflare\x00\x00\x10\x00\x03"
+            .to_vec();
+        data.extend_from_slice(b"\xc0\x0c\x00\x10\x00\x03\x00\x00\x00\x00\x00\x0c\x0b203.0.113.7");
+        data.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01\x00\x00\x00\x3c\x00\x04\xcb\x00\x71\x07");
+
+        let response = Message::decode(&data).unwrap();
+        assert_eq!(response.id, 0xabcd);
+        assert_eq!(response.rcode(), 0);
+        assert_eq!(response.questions[0].qclass, CLASS_CH);
+        assert_eq!(response.answers.len(), 2);
+        assert_eq!(response.answers[0].name, "whoami.cloudflare");
+        assert_eq!(
+            response.answers[0].txt(),
+            Some(vec![String::from("203.0.113.7")])
+        );
+        assert_eq!(
+            response.answers[1].address(),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(response.answers[1].ttl, 60);
+
+        // Pointer loop
+        let mut data = data[..HEADER_LENGTH].to_vec();
+        data[5] = 1;
+        data[7] = 0;
+        data.extend_from_slice(b"\xc0\x0c\x00\x01\x00\x01");
+        assert!(Message::decode(&data).is_err());
+        assert!(Message::decode(&data[..6]).is_err());
+    }
+
+    #[test]
+    fn test_exchange_udp_and_tcp() {
+        async_std::task::block_on(async {
+            let udp_server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let server_address = udp_server.local_addr().unwrap();
+            let tcp_server = async_std::net::TcpListener::bind(server_address)
+                .await
+                .unwrap();
+
+            let answer = ResourceRecord {
+                name: String::from("myip.opendns.com"),
+                rtype: TYPE_AAAA,
+                rclass: CLASS_IN,
+                ttl: 0,
+                data: "2001:db8::7".parse::<Ipv6Addr>().unwrap().octets().to_vec(),
+            };
+            let expected = answer.clone();
+            let responder = async_std::task::spawn(async move {
+                // UDP response is truncated and the answer is sent by TCP
+                let mut buffer = [0_u8; 512];
+                let (size, from) = udp_server.recv_from(&mut buffer).await.unwrap();
+                let mut response = Message::decode(&buffer[..size]).unwrap();
+                response.flags |= FLAG_QR | FLAG_TC;
+                udp_server
+                    .send_to(&response.encode().unwrap(), from)
+                    .await
+                    .unwrap();
+
+                let (mut stream, _) = tcp_server.accept().await.unwrap();
+                let mut length = [0_u8; 2];
+                stream.read_exact(&mut length).await.unwrap();
+                let mut buffer = vec![0_u8; u16::from_be_bytes(length) as usize];
+                stream.read_exact(&mut buffer).await.unwrap();
+                let mut response = Message::decode(&buffer).unwrap();
+                response.flags |= FLAG_QR;
+                response.answers.push(answer);
+                let data = response.encode().unwrap();
+                stream
+                    .write_all(&(data.len() as u16).to_be_bytes())
+                    .await
+                    .unwrap();
+                stream.write_all(&data).await.unwrap();
+            });
+
+            let request = Message::query(7, "myip.opendns.com", TYPE_AAAA, CLASS_IN)
+                .encode()
+                .unwrap();
+            let response = exchange(server_address, &request, Duration::from_secs(3))
+                .await
+                .unwrap();
+            assert_eq!(response.answers, vec![expected]);
+            responder.await;
+        });
+    }
+}
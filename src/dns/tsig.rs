@@ -0,0 +1,381 @@
+use std::str::FromStr;
+
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha512};
+
+use super::{
+    decode_name, decode_records, encode_name, rcode_name, read_u16, Message, ResourceRecord,
+    CLASS_ANY, HEADER_LENGTH, TYPE_TSIG,
+};
+
+/// Permitted error of time signed in seconds, recommended by RFC 8945.
+const FUDGE: u16 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TsigAlgorithm {
+    #[default]
+    HmacSha256,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TsigAlgorithm::HmacSha256 => "hmac-sha256",
+            TsigAlgorithm::HmacSha512 => "hmac-sha512",
+        }
+    }
+}
+
+impl FromStr for TsigAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().trim_end_matches('.') {
+            "hmac-sha256" => Ok(TsigAlgorithm::HmacSha256),
+            "hmac-sha512" => Ok(TsigAlgorithm::HmacSha512),
+            _ => Err(format!("unsupported TSIG algorithm {}", s)),
+        }
+    }
+}
+
+/// Key of transaction signature(RFC 8945).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsigKey {
+    name: String,
+    algorithm: TsigAlgorithm,
+    secret: Vec<u8>,
+}
+
+fn canonical_name(name: &str) -> Result<Vec<u8>, String> {
+    let mut ret = vec![];
+    encode_name(&name.to_ascii_lowercase(), &mut ret)?;
+    Ok(ret)
+}
+
+/// Fields of TSIG record data, algorithm name is kept in wire format.
+struct TsigData {
+    algorithm: Vec<u8>,
+    time_signed: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    original_id: u16,
+    error: u16,
+    other: Vec<u8>,
+}
+
+fn parse_data(data: &[u8]) -> Option<TsigData> {
+    // algorithm name is never compressed
+    let mut offset = 0;
+    loop {
+        let length = *data.get(offset)? as usize;
+        offset += 1 + length;
+        if length == 0 {
+            break;
+        }
+    }
+    let algorithm = data.get(..offset)?.to_vec();
+    let mut time_signed = [0_u8; 8];
+    time_signed[2..].copy_from_slice(data.get(offset..offset + 6)?);
+    let fudge = read_u16(data, offset + 6).ok()?;
+    let mac_size = read_u16(data, offset + 8).ok()? as usize;
+    offset += 10;
+    let mac = data.get(offset..offset + mac_size)?.to_vec();
+    offset += mac_size;
+    let other_length = read_u16(data, offset + 4).ok()? as usize;
+
+    Some(TsigData {
+        algorithm,
+        time_signed: u64::from_be_bytes(time_signed),
+        fudge,
+        mac,
+        original_id: read_u16(data, offset).ok()?,
+        error: read_u16(data, offset + 2).ok()?,
+        other: data.get(offset + 6..offset + 6 + other_length)?.to_vec(),
+    })
+}
+
+/// Offset and content of TSIG record, which must be the last additional record of the message.
+fn find_record(data: &[u8]) -> Result<(usize, ResourceRecord), String> {
+    if data.len() < HEADER_LENGTH || read_u16(data, 10)? == 0 {
+        return Err(String::from("message is not signed"));
+    }
+
+    let mut offset = HEADER_LENGTH;
+    for _ in 0..read_u16(data, 4)? {
+        offset = decode_name(data, offset)?.1 + 4;
+    }
+    decode_records(data, &mut offset, read_u16(data, 6)?)?;
+    decode_records(data, &mut offset, read_u16(data, 8)?)?;
+    decode_records(data, &mut offset, read_u16(data, 10)? - 1)?;
+    let start = offset;
+    match decode_records(data, &mut offset, 1)?.pop() {
+        Some(record) if record.rtype == TYPE_TSIG => Ok((start, record)),
+        _ => Err(String::from("message is not signed")),
+    }
+}
+
+impl TsigKey {
+    /// Create key with secret encoded by base64, which is the format used by BIND and Knot.
+    pub fn new(name: &str, algorithm: TsigAlgorithm, secret: &str) -> Result<Self, String> {
+        let secret = base64::engine::general_purpose::STANDARD
+            .decode(secret.trim())
+            .map_err(|e| format!("invalid base64 secret, {}", e))?;
+        if secret.is_empty() {
+            return Err(String::from("empty secret"));
+        }
+
+        Ok(TsigKey {
+            name: name.to_string(),
+            algorithm,
+            secret,
+        })
+    }
+
+    fn mac(&self, data: &[&[u8]]) -> Vec<u8> {
+        fn compute<M: Mac>(mut mac: M, data: &[&[u8]]) -> Vec<u8> {
+            for d in data {
+                mac.update(d);
+            }
+            mac.finalize().into_bytes().to_vec()
+        }
+
+        // HMAC accepts keys of any length
+        match self.algorithm {
+            TsigAlgorithm::HmacSha256 => compute(
+                <Hmac<Sha256> as Mac>::new_from_slice(&self.secret).unwrap(),
+                data,
+            ),
+            TsigAlgorithm::HmacSha512 => compute(
+                <Hmac<Sha512> as Mac>::new_from_slice(&self.secret).unwrap(),
+                data,
+            ),
+        }
+    }
+
+    /// TSIG variables: name, class, ttl, algorithm, time signed, fudge, error and other data.
+    fn variables(
+        &self,
+        time_signed: u64,
+        fudge: u16,
+        error: u16,
+        other: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let mut ret = canonical_name(&self.name)?;
+        ret.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        ret.extend_from_slice(&0_u32.to_be_bytes());
+        ret.extend_from_slice(&canonical_name(self.algorithm.name())?);
+        ret.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+        ret.extend_from_slice(&fudge.to_be_bytes());
+        ret.extend_from_slice(&error.to_be_bytes());
+        ret.extend_from_slice(&(other.len() as u16).to_be_bytes());
+        ret.extend_from_slice(other);
+        Ok(ret)
+    }
+
+    /// Encode message with a TSIG record appended, `time_signed` is seconds since epoch.
+    pub fn sign(&self, message: &Message, time_signed: u64) -> Result<Vec<u8>, String> {
+        self.sign_with_request_mac(message, time_signed, None)
+    }
+
+    /// Sign response to a signed request like DNS servers do.
+    #[cfg(test)]
+    pub fn sign_response(
+        &self,
+        message: &Message,
+        request: &[u8],
+        time_signed: u64,
+    ) -> Result<Vec<u8>, String> {
+        let request_mac = parse_data(&find_record(request)?.1.data)
+            .ok_or("invalid TSIG record of request")?
+            .mac;
+        self.sign_with_request_mac(message, time_signed, Some(&request_mac))
+    }
+
+    /// MAC of response covers MAC of the request before the message.
+    fn sign_with_request_mac(
+        &self,
+        message: &Message,
+        time_signed: u64,
+        request_mac: Option<&[u8]>,
+    ) -> Result<Vec<u8>, String> {
+        let data = message.encode()?;
+        let variables = self.variables(time_signed, FUDGE, 0, &[])?;
+        let mac = match request_mac {
+            Some(v) => self.mac(&[&(v.len() as u16).to_be_bytes(), v, &data, &variables]),
+            None => self.mac(&[&data, &variables]),
+        };
+
+        let mut rdata = canonical_name(self.algorithm.name())?;
+        rdata.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+        rdata.extend_from_slice(&FUDGE.to_be_bytes());
+        rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+        rdata.extend_from_slice(&mac);
+        rdata.extend_from_slice(&message.id.to_be_bytes());
+        rdata.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut signed = message.clone();
+        signed.additionals.push(ResourceRecord {
+            name: self.name.to_ascii_lowercase(),
+            rtype: TYPE_TSIG,
+            rclass: CLASS_ANY,
+            ttl: 0,
+            data: rdata,
+        });
+        signed.encode()
+    }
+
+    /// Verify TSIG of raw response by MAC of the signed request, `now` is seconds since epoch.
+    pub fn verify(&self, response: &[u8], request: &[u8], now: u64) -> Result<(), String> {
+        let request_mac = parse_data(&find_record(request)?.1.data)
+            .ok_or("invalid TSIG record of request")?
+            .mac;
+        let (start, record) = find_record(response)?;
+        let tsig = parse_data(&record.data).ok_or("invalid TSIG record")?;
+        if !record
+            .name
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(self.name.trim_end_matches('.'))
+            || tsig.algorithm.to_ascii_lowercase() != canonical_name(self.algorithm.name())?
+        {
+            return Err(String::from("response is signed by another key"));
+        }
+        if tsig.error != 0 {
+            return Err(format!("TSIG error {}", rcode_name(tsig.error)));
+        }
+
+        // MAC covers the message with original id and without TSIG record
+        let mut data = response[..start].to_vec();
+        data[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+        data[10..12].copy_from_slice(&(read_u16(response, 10)? - 1).to_be_bytes());
+        let variables = self.variables(tsig.time_signed, tsig.fudge, tsig.error, &tsig.other)?;
+        let mac = self.mac(&[
+            &(request_mac.len() as u16).to_be_bytes(),
+            &request_mac,
+            &data,
+            &variables,
+        ]);
+        // compare in constant time
+        if mac.len() != tsig.mac.len()
+            || mac
+                .iter()
+                .zip(&tsig.mac)
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                != 0
+        {
+            return Err(String::from("MAC of response mismatches"));
+        }
+        if now.abs_diff(tsig.time_signed) > u64::from(tsig.fudge) {
+            return Err(String::from("time signed of response is out of fudge"));
+        }
+        Ok(())
+    }
+}
+
+/// Error of TSIG record in response, such as BADSIG, BADKEY and BADTIME.
+pub fn response_error(response: &Message) -> Option<u16> {
+    let record = response.additionals.iter().find(|r| r.rtype == TYPE_TSIG)?;
+    parse_data(&record.data).map(|tsig| tsig.error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{FLAG_QR, TYPE_A};
+
+    #[test]
+    fn test_sign() {
+        // secret of "ddns-cli-test-key"
+        let key = TsigKey::new(
+            "Update-Key.",
+            TsigAlgorithm::HmacSha256,
+            "ZGRucy1jbGktdGVzdC1rZXk=",
+        )
+        .unwrap();
+        let mut message = Message::update(0x1234, "example.com");
+        message.authorities.push(ResourceRecord {
+            name: String::from("home.example.com"),
+            rtype: TYPE_A,
+            rclass: CLASS_ANY,
+            ttl: 0,
+            data: vec![],
+        });
+        let data = message.encode().unwrap();
+        let signed = key.sign(&message, 0x0102_0304_0506).unwrap();
+
+        let decoded = Message::decode(&signed).unwrap();
+        assert_eq!(decoded.additionals.len(), 1);
+        let record = &decoded.additionals[0];
+        assert_eq!(record.name, "update-key");
+        assert_eq!(record.rtype, TYPE_TSIG);
+        assert_eq!(record.rclass, CLASS_ANY);
+
+        let mut rdata = b"\x0bhmac-sha256\x00\x01\x02\x03\x04\x05\x06\x01\x2c\x00\x20".to_vec();
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(b"ddns-cli-test-key").unwrap();
+        mac.update(&data);
+        mac.update(b"\x0aupdate-key\x00\x00\xff\x00\x00\x00\x00");
+        mac.update(b"\x0bhmac-sha256\x00\x01\x02\x03\x04\x05\x06\x01\x2c\x00\x00\x00\x00");
+        rdata.extend_from_slice(&mac.finalize().into_bytes());
+        rdata.extend_from_slice(b"\x12\x34\x00\x00\x00\x00");
+        assert_eq!(record.data, rdata);
+        assert_eq!(response_error(&decoded), Some(0));
+
+        // BADKEY
+        let mut response = decoded.clone();
+        let length = response.additionals[0].data.len();
+        response.additionals[0].data[length - 3] = 17;
+        assert_eq!(response_error(&response), Some(17));
+
+        let key = TsigKey::new("key", TsigAlgorithm::HmacSha512, "ZGRucy1jbGk=").unwrap();
+        let signed = Message::decode(&key.sign(&message, 0).unwrap()).unwrap();
+        // algorithm name(13) + time signed(6) + fudge(2) + mac size(2) + mac(64) + id(2) + error(2) + other(2)
+        assert_eq!(signed.additionals[0].data.len(), 93);
+
+        assert!(TsigKey::new("key", TsigAlgorithm::HmacSha256, "not base64!").is_err());
+        assert_eq!(
+            "HMAC-SHA512.".parse::<TsigAlgorithm>(),
+            Ok(TsigAlgorithm::HmacSha512)
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let key = TsigKey::new(
+            "update-key",
+            TsigAlgorithm::HmacSha256,
+            "ZGRucy1jbGktdGVzdC1rZXk=",
+        )
+        .unwrap();
+        let message = Message::update(0x1234, "example.com");
+        let request = key.sign(&message, 1000).unwrap();
+        let mut response = message.clone();
+        response.flags |= FLAG_QR;
+        let signed = key.sign_response(&response, &request, 1010).unwrap();
+
+        // MAC size and MAC of request, response without TSIG record and TSIG variables
+        let request_mac = &Message::decode(&request).unwrap().additionals[0].data[23..55];
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(b"ddns-cli-test-key").unwrap();
+        mac.update(b"\x00\x20");
+        mac.update(request_mac);
+        mac.update(&response.encode().unwrap());
+        mac.update(b"\x0aupdate-key\x00\x00\xff\x00\x00\x00\x00");
+        mac.update(b"\x0bhmac-sha256\x00\x00\x00\x00\x00\x03\xf2\x01\x2c\x00\x00\x00\x00");
+        assert_eq!(
+            &Message::decode(&signed).unwrap().additionals[0].data[23..55],
+            &mac.finalize().into_bytes()[..]
+        );
+        assert_eq!(key.verify(&signed, &request, 1010), Ok(()));
+
+        // Time out of fudge, tampered rcode, response of another request and unsigned response
+        assert!(key.verify(&signed, &request, 1311).is_err());
+        let mut tampered = signed.clone();
+        tampered[3] |= 5;
+        assert!(key.verify(&tampered, &request, 1010).is_err());
+        let another_request = key.sign(&message, 1001).unwrap();
+        assert!(key.verify(&signed, &another_request, 1010).is_err());
+        assert!(key
+            .verify(&response.encode().unwrap(), &request, 1010)
+            .is_err());
+    }
+}
@@ -0,0 +1,549 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use serde::Deserialize;
+
+extern crate clap;
+use clap::{Arg, ArgMatches, Command};
+
+use base64::Engine;
+
+use super::super::daemon;
+use super::super::option;
+use super::sign;
+use super::{DomainPlan, Driver, DriverResult, ManagedDomain, PlanAction, PlanResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+type HttpMethod = super::HttpMethod;
+
+static ALIYUN_DEFAULT_ENDPOINT: &str = "https://alidns.aliyuncs.com";
+static ALIYUN_API_VERSION: &str = "2015-01-09";
+static ALIYUN_PAGE_SIZE: &str = "500";
+
+#[derive(Default)]
+pub struct Aliyun {
+    endpoint: String,
+    access_key_id: String,
+    access_key_secret: String,
+    domain: String,
+    sub_domain: ManagedDomain,
+    logger: Option<slog::Logger>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+struct AliyunRecord {
+    pub record_id: String,
+    pub r#type: String,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AliyunDomainRecords {
+    #[serde(default)]
+    pub record: Vec<AliyunRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AliyunDescribeResponse {
+    pub domain_records: AliyunDomainRecords,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+struct AliyunErrorResponse {
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+/// Sign parameters by HMAC-SHA1 of RPC style API, and return the query string.
+fn signed_query(access_key_secret: &str, params: &[(&str, &str)]) -> String {
+    let query = sign::canonical_query(params);
+    let string_to_sign = format!(
+        "GET&{}&{}",
+        sign::uri_encode("/", true),
+        sign::uri_encode(&query, true)
+    );
+    let signature = base64::engine::general_purpose::STANDARD.encode(sign::hmac_sha1(
+        format!("{}&", access_key_secret).as_bytes(),
+        string_to_sign.as_bytes(),
+    ));
+    format!("{}&Signature={}", query, sign::uri_encode(&signature, true))
+}
+
+fn record_values(recs: &[Record]) -> Vec<(&'static str, String)> {
+    recs.iter()
+        .map(|r| match r {
+            Record::A(v) => ("A", v.to_string()),
+            Record::Aaaa(v) => ("AAAA", v.to_string()),
+            Record::Cname(v) => ("CNAME", v.clone()),
+            Record::Mx(v) => ("MX", v.clone()),
+            Record::Txt(v) => ("TXT", v.clone()),
+        })
+        .collect()
+}
+
+fn should_keep(new_values: &[(&'static str, String)], old_record: &AliyunRecord) -> bool {
+    new_values
+        .iter()
+        .any(|(t, v)| *t == old_record.r#type && *v == old_record.value)
+}
+
+fn already_exists(old_records: &[AliyunRecord], r#type: &str, value: &str) -> bool {
+    old_records
+        .iter()
+        .any(|r| r.r#type == r#type && r.value == value)
+}
+
+impl Driver for Aliyun {
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("ali-domain")
+                .long("ali-domain")
+                .value_name("DOMAIN")
+                .help("Set domain of Aliyun DNS API"),
+        ).arg(
+            Arg::new("ali-name")
+                .long("ali-name")
+                .value_name("SUB DOMAIN NAME")
+                .help("Set sub domain name of Aliyun DNS API, using @ if it's not set, NAME:A or NAME:AAAA to only update records of these types"),
+        ).arg(
+            Arg::new("ali-access-key-id")
+                .long("ali-access-key-id")
+                .value_name("ACCESS KEY ID")
+                .help("Set AccessKey ID of Aliyun DNS API, you can get it from https://ram.console.aliyun.com/manage/ak"),
+        ).arg(
+            Arg::new("ali-access-key-secret")
+                .long("ali-access-key-secret")
+                .value_name("ACCESS KEY SECRET")
+                .help("Set AccessKey Secret of Aliyun DNS API, you can get it from https://ram.console.aliyun.com/manage/ak"),
+        ).arg(
+            Arg::new("ali-endpoint")
+                .long("ali-endpoint")
+                .value_name("URL")
+                .help("Set endpoint of Aliyun DNS API, https://alidns.aliyuncs.com is used if it's not set"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        self.domain = option::unwraper_option_or(matches, "ali-domain", String::default());
+        self.access_key_id =
+            option::unwraper_option_or(matches, "ali-access-key-id", String::default());
+        self.access_key_secret =
+            option::unwraper_option_or(matches, "ali-access-key-secret", String::default());
+        self.endpoint = option::unwraper_option_or(
+            matches,
+            "ali-endpoint",
+            String::from(ALIYUN_DEFAULT_ENDPOINT),
+        );
+        let sub_domain = option::unwraper_option_or(matches, "ali-name", String::from("@"));
+        self.sub_domain = match sub_domain.parse::<ManagedDomain>() {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    options.create_logger("Aliyun"),
+                    "Invalid sub domain name {}, {}", sub_domain, e
+                );
+                return;
+            }
+        };
+
+        if !self.domain.is_empty()
+            && !self.access_key_id.is_empty()
+            && !self.access_key_secret.is_empty()
+        {
+            self.logger = Some(options.create_logger("Aliyun"));
+        }
+    }
+
+    fn state_keys(&self) -> Vec<String> {
+        if self.logger.is_none() {
+            return vec![];
+        }
+
+        vec![format!("aliyun:{}:{}", self.domain, self.sub_domain)]
+    }
+
+    fn run<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, DriverResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() {
+            return future::ready(Ok(0)).boxed();
+        }
+
+        self.update(options.clone(), recs).boxed()
+    }
+
+    fn plan<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, PlanResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() {
+            return future::ready(Ok(vec![])).boxed();
+        }
+
+        self.plan_update(options.clone(), recs).boxed()
+    }
+}
+
+impl Aliyun {
+    fn logger(&self) -> &slog::Logger {
+        self.logger.as_ref().unwrap()
+    }
+
+    fn full_domain(&self) -> String {
+        if self.sub_domain.name == "@" {
+            self.domain.clone()
+        } else {
+            format!("{}.{}", self.sub_domain.name, self.domain)
+        }
+    }
+
+    /// Send signed request and return the response body, or the error message.
+    async fn request(
+        &self,
+        options: &SharedProgramOptions,
+        action: &str,
+        params: &[(&str, &str)],
+    ) -> Result<String, String> {
+        let nonce = format!("{:016x}", daemon::random_u64());
+        let timestamp = sign::iso8601(&time::OffsetDateTime::now_utc());
+        let mut query = vec![
+            ("Action", action),
+            ("Format", "JSON"),
+            ("Version", ALIYUN_API_VERSION),
+            ("AccessKeyId", self.access_key_id.as_str()),
+            ("SignatureMethod", "HMAC-SHA1"),
+            ("SignatureVersion", "1.0"),
+            ("SignatureNonce", nonce.as_str()),
+            ("Timestamp", timestamp.as_str()),
+        ];
+        query.extend_from_slice(params);
+        let url = format!(
+            "{}/?{}",
+            self.endpoint.trim_end_matches('/'),
+            signed_query(&self.access_key_secret, &query)
+        );
+
+        let rsp = options
+            .http(HttpMethod::Get, &url)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = rsp.status();
+        let rsp_text = rsp.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            let error = serde_json::from_str::<AliyunErrorResponse>(&rsp_text).unwrap_or_default();
+            return Err(format!("{} {}: {}", status, error.code, error.message));
+        }
+        Ok(rsp_text)
+    }
+
+    async fn get_records(&self, options: &SharedProgramOptions) -> Option<Vec<AliyunRecord>> {
+        let sub_domain = self.full_domain();
+        let rsp_text = match self
+            .request(
+                options,
+                "DescribeSubDomainRecords",
+                &[
+                    ("SubDomain", sub_domain.as_str()),
+                    ("PageSize", ALIYUN_PAGE_SIZE),
+                ],
+            )
+            .await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    self.logger(),
+                    "Describe records of {} failed, error: {}", sub_domain, e
+                );
+                return None;
+            }
+        };
+
+        match serde_json::from_str::<AliyunDescribeResponse>(&rsp_text) {
+            // records of types not managed are left untouched
+            Ok(v) => Some(
+                v.domain_records
+                    .record
+                    .into_iter()
+                    .filter(|r| self.sub_domain.manages(&r.r#type))
+                    .collect(),
+            ),
+            Err(e) => {
+                error!(
+                    self.logger(),
+                    "Parse response body failed, error: {}.\nbody: {}", e, rsp_text
+                );
+                None
+            }
+        }
+    }
+
+    async fn plan_update(&mut self, options: SharedProgramOptions, recs: &[Record]) -> PlanResult {
+        let new_values = record_values(&self.sub_domain.select(recs));
+        let old_records = self.get_records(&options).await.ok_or(())?;
+
+        let mut plan = DomainPlan::new("aliyun", self.full_domain());
+        for old_record in &old_records {
+            let action = if should_keep(&new_values, old_record) {
+                PlanAction::Keep
+            } else {
+                PlanAction::Delete
+            };
+            plan.add(action, &old_record.r#type, &old_record.value);
+        }
+
+        for (r#type, value) in &new_values {
+            if !already_exists(&old_records, r#type, value) {
+                plan.add(PlanAction::Create, r#type, value);
+            }
+        }
+
+        Ok(vec![plan])
+    }
+
+    async fn update(&mut self, options: SharedProgramOptions, recs: &[Record]) -> DriverResult {
+        let logger = self.logger();
+        let recs = self.sub_domain.select(recs);
+        let new_values = record_values(&recs);
+        let old_records = match self.get_records(&options).await {
+            Some(v) => v,
+            None => return Err(()),
+        };
+
+        let mut pending_to_delete: Vec<&AliyunRecord> = old_records
+            .iter()
+            .filter(|r| !should_keep(&new_values, r))
+            .collect();
+        let pending_to_create: Vec<&(&'static str, String)> = new_values
+            .iter()
+            .filter(|(t, v)| !already_exists(&old_records, t, v))
+            .collect();
+
+        if !old_records.is_empty() {
+            debug!(logger, "Old records:");
+            for log_item in &old_records {
+                debug!(logger, "     -- {:?}", log_item);
+            }
+        }
+        if !pending_to_delete.is_empty() {
+            debug!(logger, "Pending to delete:");
+            for log_item in &pending_to_delete {
+                debug!(logger, "     -- {:?}", log_item);
+            }
+        }
+        if !pending_to_create.is_empty() {
+            debug!(logger, "Pending to create:");
+            for log_item in &pending_to_create {
+                debug!(logger, "     -- {:?}", log_item);
+            }
+        }
+
+        let mut failed_count: i32 = 0;
+        // Records no more needed are updated to new values first, to reduce API calls
+        for (r#type, value) in pending_to_create {
+            let reused = if pending_to_delete.is_empty() {
+                None
+            } else {
+                Some(pending_to_delete.remove(0))
+            };
+            let result = match reused {
+                Some(old_record) => {
+                    self.request(
+                        &options,
+                        "UpdateDomainRecord",
+                        &[
+                            ("RecordId", old_record.record_id.as_str()),
+                            ("RR", self.sub_domain.name.as_str()),
+                            ("Type", r#type),
+                            ("Value", value.as_str()),
+                        ],
+                    )
+                    .await
+                }
+                None => {
+                    self.request(
+                        &options,
+                        "AddDomainRecord",
+                        &[
+                            ("DomainName", self.domain.as_str()),
+                            ("RR", self.sub_domain.name.as_str()),
+                            ("Type", r#type),
+                            ("Value", value.as_str()),
+                        ],
+                    )
+                    .await
+                }
+            };
+            match result {
+                Ok(_) => debug!(
+                    logger,
+                    "{} {} for {} success",
+                    if reused.is_some() { "Update" } else { "Create" },
+                    value,
+                    self.full_domain()
+                ),
+                Err(e) => {
+                    failed_count += 1;
+                    error!(
+                        logger,
+                        "Set {} for {} failed, error: {}",
+                        value,
+                        self.full_domain(),
+                        e
+                    );
+                }
+            }
+        }
+
+        for old_record in pending_to_delete {
+            match self
+                .request(
+                    &options,
+                    "DeleteDomainRecord",
+                    &[("RecordId", old_record.record_id.as_str())],
+                )
+                .await
+            {
+                Ok(_) => debug!(
+                    logger,
+                    "Delete {} for {} success",
+                    old_record.value,
+                    self.full_domain()
+                ),
+                Err(e) => {
+                    failed_count += 1;
+                    error!(
+                        logger,
+                        "Delete {} for {} failed, error: {}",
+                        old_record.value,
+                        self.full_domain(),
+                        e
+                    );
+                }
+            }
+        }
+
+        let action_description: Vec<String> = recs.iter().map(|r| r.to_string()).collect();
+        if failed_count > 0 {
+            error!(
+                logger,
+                "Update domain name {} to {} with {} error(s)",
+                self.full_domain(),
+                action_description.join(","),
+                failed_count
+            );
+            Err(())
+        } else {
+            info!(
+                logger,
+                "Update domain name {} to {} finished",
+                self.full_domain(),
+                action_description.join(",")
+            );
+            Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_signed_query() {
+        // Example of signature in documents of Aliyun
+        let query = signed_query(
+            "testsecret",
+            &[
+                ("Format", "XML"),
+                ("AccessKeyId", "testid"),
+                ("Action", "DescribeDomainRecords"),
+                ("SignatureMethod", "HMAC-SHA1"),
+                ("DomainName", "example.com"),
+                ("SignatureNonce", "f59ed6a9-83fc-473b-9cc6-99c95df3856e"),
+                ("SignatureVersion", "1.0"),
+                ("Version", "2015-01-09"),
+                ("Timestamp", "2016-03-24T16:41:54Z"),
+            ],
+        );
+        assert!(query.starts_with("AccessKeyId=testid&Action=DescribeDomainRecords&"));
+        assert!(query.ends_with("&Signature=uRpHwaSEt3J%2B6KQD%2F%2FsvCh%2Fx%2BpI%3D"));
+    }
+
+    #[test]
+    fn test_update() {
+        const RECORDS: &str = r#"{"TotalCount":0,"PageSize":500,"PageNumber":1,"RequestId":"R1","DomainRecords":{"Record":[]}}"#;
+        const ERROR: &str = r#"{"RequestId":"R2","Code":"DomainRecordDuplicate","Message":"The DNS record already exists."}"#;
+
+        async_std::task::block_on(async {
+            let (address, server) = test_util::http_stand_in(vec![
+                (200, String::from(RECORDS)),
+                (400, String::from(ERROR)),
+            ])
+            .await;
+
+            let options = test_util::default_options(Duration::from_secs(3));
+            let mut driver = Aliyun {
+                endpoint: format!("http://{}", address),
+                access_key_id: String::from("testid"),
+                access_key_secret: String::from("testsecret"),
+                domain: String::from("example.com"),
+                sub_domain: "@".parse().unwrap(),
+                logger: Some(slog::Logger::root(slog::Discard, o!())),
+            };
+            let recs = vec![Record::A(Ipv4Addr::new(198, 51, 100, 4))];
+            assert_eq!(driver.update(options, &recs).await, Err(()));
+
+            let requests = server.await;
+            assert_eq!(requests.len(), 2);
+            // Sub domain of root is the domain itself
+            assert!(requests[0].contains("&SubDomain=example.com&"));
+            // Nothing can be reused, so a new record is added
+            let query = requests[1]
+                .strip_prefix("GET /?")
+                .and_then(|r| r.split_once(' '))
+                .map(|(q, _)| q)
+                .unwrap();
+            assert!(query.contains("&Action=AddDomainRecord&"));
+            assert!(query.contains("&DomainName=example.com&"));
+            assert!(query.contains("&RR=%40&"));
+            assert!(query.contains("&Type=A&Value=198.51.100.4&"));
+
+            // Signature can be verified by the same parameters
+            let (params, signature) = query.rsplit_once("&Signature=").unwrap();
+            let params: Vec<(String, String)> =
+                reqwest::Url::parse(&format!("http://h/?{}", params))
+                    .unwrap()
+                    .query_pairs()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect();
+            let params: Vec<(&str, &str)> = params
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect();
+            assert!(
+                signed_query("testsecret", &params).ends_with(&format!("&Signature={}", signature))
+            );
+        });
+    }
+}
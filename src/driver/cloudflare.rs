@@ -6,7 +6,7 @@ extern crate clap;
 use clap::{Arg, ArgAction, ArgMatches, Command};
 
 use super::super::option;
-use super::{Driver, DriverResult, Record};
+use super::{DomainPlan, Driver, DriverResult, ManagedDomain, PlanAction, PlanResult, Record};
 use reqwest::header::CONTENT_TYPE;
 
 type SharedProgramOptions = super::SharedProgramOptions;
@@ -16,7 +16,7 @@ type HttpMethod = super::HttpMethod;
 pub struct Cloudflare {
     zone_id: String,
     token: String,
-    domains: Vec<String>,
+    domains: Vec<ManagedDomain>,
     logger: Option<slog::Logger>,
 }
 
@@ -38,7 +38,7 @@ impl Driver for Cloudflare {
                 .value_name("DOMAIN")
                 .num_args(1..)
                 .action(ArgAction::Append)
-                .help("Add domain to update using cloudflare API"),
+                .help("Add domain to update using cloudflare API, DOMAIN:A or DOMAIN:AAAA to only update records of these types"),
         )
     }
 
@@ -57,6 +57,17 @@ impl Driver for Cloudflare {
         }
     }
 
+    fn state_keys(&self) -> Vec<String> {
+        if self.logger.is_none() {
+            return vec![];
+        }
+
+        self.domains
+            .iter()
+            .map(|domain| format!("cloudflare:{}:{}", self.zone_id, domain))
+            .collect()
+    }
+
     fn run<'a, 'b, 'c>(
         &'a mut self,
         options: &SharedProgramOptions,
@@ -72,6 +83,22 @@ impl Driver for Cloudflare {
 
         self.update(options.clone(), recs).boxed()
     }
+
+    fn plan<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, PlanResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() {
+            return future::ready(Ok(vec![])).boxed();
+        }
+
+        self.plan_update(options.clone(), recs).boxed()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -201,137 +228,208 @@ impl CloudflareResponseResult {
     }
 }
 
-impl Cloudflare {
-    async fn update<'a, 'b>(
-        &'a mut self,
-        options: SharedProgramOptions,
-        recs: &'b [Record],
-    ) -> DriverResult
-    where
-        'b: 'a,
-    {
-        let mut ret: i32 = 0;
-        let mut actions: Vec<CloudflareRecordAction> = recs
-            .iter()
-            .map(|ele| match ele {
-                Record::A(r) => CloudflareRecordAction {
-                    record: CloudflareRecord {
-                        r#type: "A",
-                        name: String::default(),
-                        content: r.to_string(),
-                        ttl: 1,
-                        proxied: false,
-                    },
+fn create_actions(recs: &[Record]) -> Vec<CloudflareRecordAction> {
+    recs.iter()
+        .map(|ele| match ele {
+            Record::A(r) => CloudflareRecordAction {
+                record: CloudflareRecord {
+                    r#type: "A",
+                    name: String::default(),
+                    content: r.to_string(),
+                    ttl: 1,
+                    proxied: false,
                 },
-                Record::Aaaa(r) => CloudflareRecordAction {
-                    record: CloudflareRecord {
-                        r#type: "AAAA",
-                        name: String::default(),
-                        content: r.to_string(),
-                        ttl: 1,
-                        proxied: false,
-                    },
+            },
+            Record::Aaaa(r) => CloudflareRecordAction {
+                record: CloudflareRecord {
+                    r#type: "AAAA",
+                    name: String::default(),
+                    content: r.to_string(),
+                    ttl: 1,
+                    proxied: false,
                 },
-                Record::Cname(r) => CloudflareRecordAction {
-                    record: CloudflareRecord {
-                        r#type: "CNAME",
-                        name: String::default(),
-                        content: r.clone(),
-                        ttl: 1,
-                        proxied: false,
-                    },
+            },
+            Record::Cname(r) => CloudflareRecordAction {
+                record: CloudflareRecord {
+                    r#type: "CNAME",
+                    name: String::default(),
+                    content: r.clone(),
+                    ttl: 1,
+                    proxied: false,
                 },
-                Record::Mx(r) => CloudflareRecordAction {
-                    record: CloudflareRecord {
-                        r#type: "MX",
-                        name: String::default(),
-                        content: r.clone(),
-                        ttl: 1,
-                        proxied: false,
-                    },
+            },
+            Record::Mx(r) => CloudflareRecordAction {
+                record: CloudflareRecord {
+                    r#type: "MX",
+                    name: String::default(),
+                    content: r.clone(),
+                    ttl: 1,
+                    proxied: false,
                 },
-                Record::Txt(r) => CloudflareRecordAction {
-                    record: CloudflareRecord {
-                        r#type: "TXT",
-                        name: String::default(),
-                        content: r.clone(),
-                        ttl: 1,
-                        proxied: false,
-                    },
+            },
+            Record::Txt(r) => CloudflareRecordAction {
+                record: CloudflareRecord {
+                    r#type: "TXT",
+                    name: String::default(),
+                    content: r.clone(),
+                    ttl: 1,
+                    proxied: false,
                 },
-            })
-            .collect();
+            },
+        })
+        .collect()
+}
+
+fn should_keep(
+    actions: &[CloudflareRecordAction],
+    old_record: &CloudflareGetResponseRecord,
+) -> bool {
+    actions.iter().any(|act| {
+        act.record.r#type == old_record.r#type && act.record.content == old_record.content
+    })
+}
+
+fn already_exists(
+    old_records: &[CloudflareGetResponseRecord],
+    new_record: &CloudflareRecordAction,
+) -> bool {
+    old_records.iter().any(|res| {
+        res.r#type == new_record.record.r#type && res.content == new_record.record.content
+    })
+}
+
+fn plan_changes(
+    domain: &str,
+    actions: &[CloudflareRecordAction],
+    old_records: &[CloudflareGetResponseRecord],
+) -> DomainPlan {
+    let mut plan = DomainPlan::new("cloudflare", domain.to_string());
+    for old_record in old_records {
+        let action = if should_keep(actions, old_record) {
+            PlanAction::Keep
+        } else {
+            PlanAction::Delete
+        };
+        plan.add(action, &old_record.r#type, &old_record.content);
+    }
 
-        for ref domain in &self.domains {
-            let url = format!(
-                "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
-                self.zone_id
+    for new_record in actions {
+        if !already_exists(old_records, new_record) {
+            plan.add(
+                PlanAction::Create,
+                new_record.record.r#type,
+                &new_record.record.content,
             );
-            // page=1&per_page=50&order=name&name={}
-            let cli = options
-                .http(HttpMethod::Get, &url)
-                .bearer_auth(self.token.clone())
-                .query(&[
-                    ("page", "1"),
-                    ("per_page", "100"),
-                    ("order", "name"),
-                    ("name", domain),
-                ])
-                .header(CONTENT_TYPE, CFHEAD_CONTENT_TYPE);
-
-            let rsp = match cli.send().await {
-                Ok(v) => v,
-                Err(e) => {
-                    if let Some(ref logger) = self.logger {
-                        error!(logger, "Send HTTP request failed, error: {}", e);
-                    }
-                    continue;
+        }
+    }
+    plan
+}
+
+impl Cloudflare {
+    async fn get_records(
+        &self,
+        options: &SharedProgramOptions,
+        domain: &str,
+    ) -> Option<CloudflareGetResponseResult> {
+        let url = format!(
+            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
+            self.zone_id
+        );
+        // page=1&per_page=50&order=name&name={}
+        let cli = options
+            .http(HttpMethod::Get, &url)
+            .bearer_auth(self.token.clone())
+            .query(&[
+                ("page", "1"),
+                ("per_page", "100"),
+                ("order", "name"),
+                ("name", domain),
+            ])
+            .header(CONTENT_TYPE, CFHEAD_CONTENT_TYPE);
+
+        let rsp = match cli.send().await {
+            Ok(v) => v,
+            Err(e) => {
+                if let Some(ref logger) = self.logger {
+                    error!(logger, "Send HTTP request failed, error: {}", e);
                 }
-            };
+                return None;
+            }
+        };
 
-            let rsp_text = match rsp.text().await {
-                Ok(v) => v,
-                Err(e) => {
-                    if let Some(ref logger) = self.logger {
-                        error!(logger, "Fetch response body failed, error: {}", e);
-                    }
-                    continue;
+        let rsp_text = match rsp.text().await {
+            Ok(v) => v,
+            Err(e) => {
+                if let Some(ref logger) = self.logger {
+                    error!(logger, "Fetch response body failed, error: {}", e);
                 }
-            };
+                return None;
+            }
+        };
 
-            let result = match serde_json::from_str::<CloudflareGetResponseResult>(&rsp_text) {
-                Ok(v) => v,
-                Err(e) => {
-                    if let Some(ref logger) = self.logger {
-                        error!(
-                            logger,
-                            "Parse response body failed, error: {}.\nbody: {}", e, rsp_text
-                        );
-                    }
-                    continue;
+        let result = match serde_json::from_str::<CloudflareGetResponseResult>(&rsp_text) {
+            Ok(v) => v,
+            Err(e) => {
+                if let Some(ref logger) = self.logger {
+                    error!(
+                        logger,
+                        "Parse response body failed, error: {}.\nbody: {}", e, rsp_text
+                    );
                 }
+                return None;
+            }
+        };
+
+        Some(result)
+    }
+
+    async fn plan_update(&self, options: SharedProgramOptions, recs: &[Record]) -> PlanResult {
+        let mut ret = vec![];
+        for domain in &self.domains {
+            let actions = create_actions(&domain.select(recs));
+            let mut result = match self.get_records(&options, &domain.name).await {
+                Some(v) => v,
+                None => return Err(()),
             };
+            result.result.retain(|r| domain.manages(&r.r#type));
+            ret.push(plan_changes(&domain.name, &actions, &result.result));
+        }
+
+        Ok(ret)
+    }
+
+    async fn update<'a, 'b>(
+        &'a mut self,
+        options: SharedProgramOptions,
+        recs: &'b [Record],
+    ) -> DriverResult
+    where
+        'b: 'a,
+    {
+        let mut ret: i32 = 0;
+
+        for domain in &self.domains {
+            let domain_recs = domain.select(recs);
+            let mut actions = create_actions(&domain_recs);
+            let mut result = match self.get_records(&options, &domain.name).await {
+                Some(v) => v,
+                None => continue,
+            };
+            // records of types not managed are left untouched
+            result.result.retain(|r| domain.manages(&r.r#type));
 
             let mut pending_to_delete: Vec<&CloudflareGetResponseRecord> = vec![];
             let mut pending_to_create: Vec<&mut CloudflareRecordAction> = vec![];
 
             for old_record in &result.result {
-                let keep = actions.iter().any(|act| {
-                    act.record.r#type == old_record.r#type
-                        && act.record.content == old_record.content
-                });
-                if !keep {
+                if !should_keep(&actions, old_record) {
                     pending_to_delete.push(old_record);
                 }
             }
 
             for new_record in &mut actions {
-                let already_exists = result.result.iter().any(|res| {
-                    res.r#type == new_record.record.r#type
-                        && res.content == new_record.record.content
-                });
-                if !already_exists {
-                    new_record.record.name = domain.to_string();
+                if !already_exists(&result.result, new_record) {
+                    new_record.record.name = domain.name.clone();
                     pending_to_create.push(new_record);
                 }
             }
@@ -474,13 +572,14 @@ impl Cloudflare {
             }
 
             if let Some(ref logger) = self.logger {
-                let action_description: Vec<String> = recs.iter().map(|r| r.to_string()).collect();
+                let action_description: Vec<String> =
+                    domain_recs.iter().map(|r| r.to_string()).collect();
                 if failed_count > 0 {
                     ret = 1;
                     error!(
                         logger,
                         "Update domain name {} to {} with {} error(s)",
-                        domain,
+                        domain.name,
                         action_description.join(","),
                         failed_count
                     );
@@ -488,7 +587,7 @@ impl Cloudflare {
                     info!(
                         logger,
                         "Update domain name {} to {} finished",
-                        domain,
+                        domain.name,
                         action_description.join(",")
                     );
                 }
@@ -556,7 +655,7 @@ mod tests {
             }
             "#;
 
-        let result = serde_json::from_str::<CloudflareGetResponseResult>(&GET_RESPONSE).unwrap();
+        let result = serde_json::from_str::<CloudflareGetResponseResult>(GET_RESPONSE).unwrap();
         assert_eq!(result.result.len(), 2);
         assert_eq!(result.result.len(), 2);
         assert_eq!(result.result[0].name, "media.x-ha.com");
@@ -566,5 +665,30 @@ mod tests {
             result.result[1].content,
             "2400:1111:0:210:b914:1caf:89ba:d3b1"
         );
+
+        let recs = vec![
+            Record::A("1.2.3.4".parse().unwrap()),
+            Record::Aaaa("2001:db8::1".parse().unwrap()),
+        ];
+        let plan = plan_changes("media.x-ha.com", &create_actions(&recs), &result.result);
+        assert_eq!(plan.driver, "cloudflare");
+        assert_eq!(plan.domain, "media.x-ha.com");
+        let changes: Vec<(PlanAction, &str, &str)> = plan
+            .changes
+            .iter()
+            .map(|c| (c.action, c.r#type.as_str(), c.content.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (PlanAction::Keep, "A", "1.2.3.4"),
+                (
+                    PlanAction::Delete,
+                    "AAAA",
+                    "2400:1111:0:210:b914:1caf:89ba:d3b1"
+                ),
+                (PlanAction::Create, "AAAA", "2001:db8::1"),
+            ]
+        );
     }
 }
@@ -1,4 +1,6 @@
 use std::clone::Clone;
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use futures::future::{self, BoxFuture, FutureExt};
@@ -8,23 +10,62 @@ use serde::{Deserialize, Serialize};
 extern crate clap;
 use clap::{Arg, ArgMatches, Command};
 
+use reqwest::header::CONTENT_TYPE;
+
 use super::super::option;
-use super::{Driver, DriverResult, Record};
+use super::sign;
+use super::{DomainPlan, Driver, DriverResult, ManagedDomain, PlanAction, PlanResult, Record};
 
 type SharedProgramOptions = super::SharedProgramOptions;
 type HttpMethod = super::HttpMethod;
 
+/// API used by dnspod driver.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum DnspodApi {
+    /// https://dnsapi.cn/Record.* with login token
+    #[default]
+    Legacy,
+    /// Tencent Cloud API 3.0 with TC3-HMAC-SHA256 signature
+    V3,
+}
+
+impl FromStr for DnspodApi {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "legacy" => Ok(DnspodApi::Legacy),
+            "v3" => Ok(DnspodApi::V3),
+            _ => Err(format!(
+                "unsupported dnspod API {}, legacy or v3 expected",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Dnspod {
+    api: DnspodApi,
     domain_id: String,
     domain: String,
     token: String,
     token_id: String,
-    sub_domain: String,
+    secret_id: String,
+    secret_key: String,
+    endpoint: String,
+    sub_domain: ManagedDomain,
     logger: Option<slog::Logger>,
 }
 
 static DNSPOD_RESPONSE_CODE_SUCCESS: &str = "1";
+static DNSPOD_V3_DEFAULT_ENDPOINT: &str = "https://dnspod.tencentcloudapi.com";
+static DNSPOD_V3_SERVICE: &str = "dnspod";
+static DNSPOD_V3_VERSION: &str = "2021-03-23";
+static DNSPOD_V3_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+static DNSPOD_V3_RECORD_LINE: &str = "默认";
+static DNSPOD_V3_PAGE_SIZE: usize = 3000;
+static DNSPOD_V3_ERROR_NO_RECORD: &str = "ResourceNotFound.NoDataOfRecord";
 
 impl Driver for Dnspod {
     fn initialize(&mut self, app: Command) -> Command {
@@ -42,7 +83,7 @@ impl Driver for Dnspod {
             Arg::new("dp-name")
                 .long("dp-name")
                 .value_name("SUB DOMAIN NAME")
-                .help("Set sub domain name of dnspod API, using @ if it's not set"),
+                .help("Set sub domain name of dnspod API, using @ if it's not set, NAME:A or NAME:AAAA to only update records of these types"),
         ).arg(
             Arg::new("dp-token")
                 .long("dp-token")
@@ -53,6 +94,28 @@ impl Driver for Dnspod {
                 .long("dp-token-id")
                 .value_name("TOKEN_ID")
                 .help("Set token ID of dnspod API, you can get it from https://console.dnspod.cn/account/token"),
+        ).arg(
+            Arg::new("dp-api")
+                .long("dp-api")
+                .value_name("API")
+                .ignore_case(true)
+                .value_parser(["legacy", "v3"])
+                .help("Set API of dnspod, legacy uses --dp-token and v3(Tencent Cloud API 3.0) uses --dp-secret-id and --dp-secret-key, legacy is used if it's not set"),
+        ).arg(
+            Arg::new("dp-secret-id")
+                .long("dp-secret-id")
+                .value_name("SECRET ID")
+                .help("Set SecretId of Tencent Cloud API 3.0, you can get it from https://console.cloud.tencent.com/cam/capi"),
+        ).arg(
+            Arg::new("dp-secret-key")
+                .long("dp-secret-key")
+                .value_name("SECRET KEY")
+                .help("Set SecretKey of Tencent Cloud API 3.0, you can get it from https://console.cloud.tencent.com/cam/capi"),
+        ).arg(
+            Arg::new("dp-endpoint")
+                .long("dp-endpoint")
+                .value_name("URL")
+                .help("Set endpoint of Tencent Cloud API 3.0, https://dnspod.tencentcloudapi.com is used if it's not set"),
         )
     }
 
@@ -61,15 +124,61 @@ impl Driver for Dnspod {
         self.domain = option::unwraper_option_or(matches, "dp-domain", String::default());
         self.token = option::unwraper_option_or(matches, "dp-token", String::default());
         self.token_id = option::unwraper_option_or(matches, "dp-token-id", String::default());
-        self.sub_domain = option::unwraper_option_or(matches, "dp-name", String::from("@"));
+        self.secret_id = option::unwraper_option_or(matches, "dp-secret-id", String::default());
+        self.secret_key = option::unwraper_option_or(matches, "dp-secret-key", String::default());
+        self.endpoint = option::unwraper_option_or(
+            matches,
+            "dp-endpoint",
+            String::from(DNSPOD_V3_DEFAULT_ENDPOINT),
+        );
+        let api = option::unwraper_option_or(matches, "dp-api", String::from("legacy"));
+        self.api = match api.parse::<DnspodApi>() {
+            Ok(v) => v,
+            Err(e) => {
+                error!(options.create_logger("Dnspod"), "{}", e);
+                return;
+            }
+        };
+        let sub_domain = option::unwraper_option_or(matches, "dp-name", String::from("@"));
+        self.sub_domain = match sub_domain.parse::<ManagedDomain>() {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    options.create_logger("Dnspod"),
+                    "Invalid sub domain name {}, {}", sub_domain, e
+                );
+                return;
+            }
+        };
 
-        if (!self.token_id.is_empty() || !self.token.is_empty())
-            && (!self.domain_id.is_empty() || !self.domain.is_empty())
-        {
+        let enabled = match self.api {
+            DnspodApi::Legacy => {
+                (!self.token_id.is_empty() || !self.token.is_empty())
+                    && (!self.domain_id.is_empty() || !self.domain.is_empty())
+            }
+            // Domain is required by Tencent Cloud API 3.0 even if domain id is set
+            DnspodApi::V3 => {
+                !self.secret_id.is_empty() && !self.secret_key.is_empty() && !self.domain.is_empty()
+            }
+        };
+        if enabled {
             self.logger = Some(options.create_logger("Dnspod"));
         }
     }
 
+    fn state_keys(&self) -> Vec<String> {
+        if self.logger.is_none() {
+            return vec![];
+        }
+
+        let domain = if self.domain_id.is_empty() {
+            &self.domain
+        } else {
+            &self.domain_id
+        };
+        vec![format!("dnspod:{}:{}", domain, self.sub_domain)]
+    }
+
     fn run<'a, 'b, 'c>(
         &'a mut self,
         options: &SharedProgramOptions,
@@ -85,6 +194,22 @@ impl Driver for Dnspod {
 
         self.update(options.clone(), recs).boxed()
     }
+
+    fn plan<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, PlanResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() {
+            return future::ready(Ok(vec![])).boxed();
+        }
+
+        self.plan_update(options.clone(), recs).boxed()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -195,6 +320,195 @@ impl DnspodResponseResult {
     }
 }
 
+/// Error of Tencent Cloud API 3.0, code is empty if the request is not finished.
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "PascalCase")]
+struct DnspodV3Error {
+    pub code: String,
+    pub message: String,
+}
+
+impl fmt::Display for DnspodV3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.code.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            f.write_fmt(format_args!("{}: {}", self.code, self.message))
+        }
+    }
+}
+
+impl DnspodV3Error {
+    fn new<T: ToString>(message: T) -> Self {
+        DnspodV3Error {
+            code: String::default(),
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DnspodV3Record {
+    pub record_id: u64,
+    pub name: String,
+    pub line: String,
+    pub line_id: String,
+    pub r#type: String,
+    pub value: String,
+    #[serde(rename = "MX", default)]
+    pub mx: u64,
+    #[serde(rename = "TTL")]
+    pub ttl: u64,
+}
+
+impl From<DnspodV3Record> for DnspodGetResponseRecord {
+    fn from(record: DnspodV3Record) -> Self {
+        DnspodGetResponseRecord {
+            id: record.record_id.to_string(),
+            name: record.name,
+            line: record.line,
+            line_id: record.line_id,
+            r#type: record.r#type,
+            value: record.value,
+            mx: record.mx.to_string(),
+            ttl: record.ttl.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DnspodV3RecordList {
+    #[serde(default)]
+    pub record_list: Vec<DnspodV3Record>,
+}
+
+/// Authorization header of TC3-HMAC-SHA256 signature, content-type and host are signed.
+fn tc3_authorization(
+    secret_id: &str,
+    secret_key: &str,
+    host: &str,
+    payload: &str,
+    timestamp: i64,
+) -> String {
+    let signed_headers = "content-type;host";
+    let canonical_request = format!(
+        "POST\n/\n\ncontent-type:{}\nhost:{}\n\n{}\n{}",
+        DNSPOD_V3_CONTENT_TYPE,
+        host,
+        signed_headers,
+        sign::sha256_hex(payload.as_bytes())
+    );
+    let date = match time::OffsetDateTime::from_unix_timestamp(timestamp) {
+        Ok(v) => sign::iso8601(&v)[..10].to_string(),
+        Err(_) => String::default(),
+    };
+    let scope = format!("{}/{}/tc3_request", date, DNSPOD_V3_SERVICE);
+    let string_to_sign = format!(
+        "TC3-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        sign::sha256_hex(canonical_request.as_bytes())
+    );
+
+    let mut key = format!("TC3{}", secret_key).into_bytes();
+    for data in [date.as_str(), DNSPOD_V3_SERVICE, "tc3_request"] {
+        key = sign::hmac_sha256(&key, data.as_bytes());
+    }
+    let signature = hex::encode(sign::hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "TC3-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        secret_id, scope, signed_headers, signature
+    )
+}
+
+fn create_actions(recs: &[Record]) -> Vec<Arc<DnspodRecordAction>> {
+    recs.iter()
+        .map(|ele| {
+            Arc::new(match ele {
+                Record::A(r) => DnspodRecordAction {
+                    record: DnspodRecord {
+                        record_type: "A",
+                        sub_domain: String::default(),
+                        value: r.to_string(),
+                        ttl: String::from("600"),
+                        mx: String::from("10"), // 0-20
+                        domain_id: String::default(),
+                        record_line: String::default(),
+                        record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
+                    },
+                },
+                Record::Aaaa(r) => DnspodRecordAction {
+                    record: DnspodRecord {
+                        record_type: "AAAA",
+                        sub_domain: String::default(),
+                        value: r.to_string(),
+                        ttl: String::from("600"),
+                        mx: String::from("10"), // 0-20
+                        domain_id: String::default(),
+                        record_line: String::default(),
+                        record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
+                    },
+                },
+                Record::Cname(r) => DnspodRecordAction {
+                    record: DnspodRecord {
+                        record_type: "CNAME",
+                        sub_domain: String::default(),
+                        value: r.to_string(),
+                        ttl: String::from("600"),
+                        mx: String::from("10"), // 0-20
+                        domain_id: String::default(),
+                        record_line: String::default(),
+                        record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
+                    },
+                },
+                Record::Mx(r) => DnspodRecordAction {
+                    record: DnspodRecord {
+                        record_type: "MX",
+                        sub_domain: String::default(),
+                        value: r.to_string(),
+                        ttl: String::from("600"),
+                        mx: String::from("10"), // 0-20
+                        domain_id: String::default(),
+                        record_line: String::default(),
+                        record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
+                    },
+                },
+                Record::Txt(r) => DnspodRecordAction {
+                    record: DnspodRecord {
+                        record_type: "TXT",
+                        sub_domain: String::default(),
+                        value: r.to_string(),
+                        ttl: String::from("600"),
+                        mx: String::from("10"), // 0-20
+                        domain_id: String::default(),
+                        record_line: String::default(),
+                        record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
+                    },
+                },
+            })
+        })
+        .collect()
+}
+
+fn should_keep(actions: &[Arc<DnspodRecordAction>], old_record: &DnspodGetResponseRecord) -> bool {
+    actions.iter().any(|act| {
+        act.as_ref().record.record_type == old_record.r#type
+            && act.as_ref().record.value == old_record.value
+    })
+}
+
+fn already_exists(
+    old_records: &[Arc<DnspodGetResponseRecord>],
+    action: &DnspodRecordAction,
+) -> bool {
+    old_records
+        .iter()
+        .any(|res| res.r#type == action.record.record_type && res.value == action.record.value)
+}
+
 impl Dnspod {
     fn generate_common_form(&self) -> reqwest::multipart::Form {
         let api_token_parameter = if self.token_id.is_empty() {
@@ -214,6 +528,38 @@ impl Dnspod {
         }
     }
 
+    async fn plan_update(&mut self, options: SharedProgramOptions, recs: &[Record]) -> PlanResult {
+        let actions = create_actions(&self.sub_domain.select(recs));
+        let old_records = self.get_records(options).await.ok_or(())?;
+
+        let domain = if self.sub_domain.name == "@" {
+            self.domain.clone()
+        } else {
+            format!("{}.{}", self.sub_domain.name, self.domain)
+        };
+        let mut plan = DomainPlan::new("dnspod", domain);
+        for old_record in &old_records {
+            let action = if should_keep(&actions, old_record) {
+                PlanAction::Keep
+            } else {
+                PlanAction::Delete
+            };
+            plan.add(action, &old_record.r#type, &old_record.value);
+        }
+
+        for action in &actions {
+            if !already_exists(&old_records, action) {
+                plan.add(
+                    PlanAction::Create,
+                    action.record.record_type,
+                    &action.record.value,
+                );
+            }
+        }
+
+        Ok(vec![plan])
+    }
+
     async fn update<'a, 'b>(
         &'a mut self,
         options: SharedProgramOptions,
@@ -224,100 +570,40 @@ impl Dnspod {
     {
         // Common parameters: login_token=LOGIN_TOKEN&format=json&lang=en
         let mut ret: i32 = 0;
-        let actions: Vec<Arc<DnspodRecordAction>> = recs
-            .iter()
-            .map(|ele| {
-                Arc::new(match ele {
-                    Record::A(r) => DnspodRecordAction {
-                        record: DnspodRecord {
-                            record_type: "A",
-                            sub_domain: String::default(),
-                            value: r.to_string(),
-                            ttl: String::from("600"),
-                            mx: String::from("10"), // 0-20
-                            domain_id: String::default(),
-                            record_line: String::default(),
-                            record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
-                        },
-                    },
-                    Record::Aaaa(r) => DnspodRecordAction {
-                        record: DnspodRecord {
-                            record_type: "AAAA",
-                            sub_domain: String::default(),
-                            value: r.to_string(),
-                            ttl: String::from("600"),
-                            mx: String::from("10"), // 0-20
-                            domain_id: String::default(),
-                            record_line: String::default(),
-                            record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
-                        },
-                    },
-                    Record::Cname(r) => DnspodRecordAction {
-                        record: DnspodRecord {
-                            record_type: "CNAME",
-                            sub_domain: String::default(),
-                            value: r.to_string(),
-                            ttl: String::from("600"),
-                            mx: String::from("10"), // 0-20
-                            domain_id: String::default(),
-                            record_line: String::default(),
-                            record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
-                        },
-                    },
-                    Record::Mx(r) => DnspodRecordAction {
-                        record: DnspodRecord {
-                            record_type: "MX",
-                            sub_domain: String::default(),
-                            value: r.to_string(),
-                            ttl: String::from("600"),
-                            mx: String::from("10"), // 0-20
-                            domain_id: String::default(),
-                            record_line: String::default(),
-                            record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
-                        },
-                    },
-                    Record::Txt(r) => DnspodRecordAction {
-                        record: DnspodRecord {
-                            record_type: "TXT",
-                            sub_domain: String::default(),
-                            value: r.to_string(),
-                            ttl: String::from("600"),
-                            mx: String::from("10"), // 0-20
-                            domain_id: String::default(),
-                            record_line: String::default(),
-                            record_line_id: String::from("0"), // @see https://docs.dnspod.cn/api/5f5623f9e75cf42d25bf6776/
-                        },
-                    },
-                })
-            })
-            .collect();
+        let recs = self.sub_domain.select(recs);
+        let actions = create_actions(&recs);
+        // Records would be created again if old ones are unknown
+        let old_records = match self.get_records(options.clone()).await {
+            Some(v) => v,
+            None => {
+                if let Some(ref logger) = self.logger {
+                    error!(
+                        logger,
+                        "Update domain name {} failed, can not get old records", self.domain
+                    );
+                }
+                return Err(());
+            }
+        };
 
         let mut failed_count: i32 = 0;
         failed_count += async {
             let mut current_failed_count: i32 = 0;
             let mut pending_to_delete: Vec<Arc<DnspodGetResponseRecord>> = vec![];
             let mut pending_to_create: Vec<Arc<DnspodRecordAction>> = vec![];
-            let old_records = self.get_records(options.clone()).await;
 
             // Initialize pending delete records
             for old_record in &old_records {
-                let keep = actions.iter().any(|act| {
-                    act.as_ref().record.record_type == old_record.r#type
-                        && act.as_ref().record.value == old_record.value
-                });
-                if !keep {
+                if !should_keep(&actions, old_record) {
                     pending_to_delete.push(old_record.clone());
                 }
             }
 
             // Initialize new records
             for action in &actions {
-                let already_exists = old_records.iter().any(|res| {
-                    res.r#type == action.record.record_type && res.value == action.record.value
-                });
-                if !already_exists {
+                if !already_exists(&old_records, action) {
                     let mut new_record = action.clone();
-                    Arc::make_mut(&mut new_record).record.sub_domain = self.sub_domain.clone();
+                    Arc::make_mut(&mut new_record).record.sub_domain = self.sub_domain.name.clone();
                     pending_to_create.push(new_record);
                 }
             }
@@ -345,6 +631,13 @@ impl Dnspod {
                 }
             }
 
+            // Tencent Cloud API 3.0 can modify records no more need to new values, to reduce API calls
+            if self.api == DnspodApi::V3 {
+                current_failed_count += self
+                    .modify_records_v3(&options, &mut pending_to_delete, &mut pending_to_create)
+                    .await;
+            }
+
             // Delete records no more need
             current_failed_count += self
                 .remove_records(options.clone(), pending_to_delete)
@@ -390,17 +683,20 @@ impl Dnspod {
     async fn get_records(
         &mut self,
         options: SharedProgramOptions,
-    ) -> Vec<Arc<DnspodGetResponseRecord>> {
+    ) -> Option<Vec<Arc<DnspodGetResponseRecord>>> {
+        if self.api == DnspodApi::V3 {
+            return self.get_records_v3(&options).await;
+        }
+
         let mut ret: Vec<Arc<DnspodGetResponseRecord>> = vec![];
 
         // Records over 100 must be request by page
-        let mut page_offset: usize = 0;
         let get_list_url = String::from("https://dnsapi.cn/Record.List");
 
         loop {
             let form = self
                 .generate_common_form()
-                .text("sub_domain", self.sub_domain.clone());
+                .text("sub_domain", self.sub_domain.name.clone());
             let cli = options
                 .http(HttpMethod::Post, &get_list_url)
                 .multipart(form);
@@ -411,7 +707,7 @@ impl Dnspod {
                     if let Some(ref logger) = self.logger {
                         error!(logger, "Send HTTP request failed, error: {}", e);
                     }
-                    break;
+                    return None;
                 }
             };
 
@@ -422,7 +718,7 @@ impl Dnspod {
                         error!(logger, "Fetch response body failed, error: {}", e);
                     }
 
-                    break;
+                    return None;
                 }
             };
 
@@ -435,7 +731,7 @@ impl Dnspod {
                             "Parse response body failed, error: {}.\nbody: {}", e, rsp_text
                         );
                     }
-                    break;
+                    return None;
                 }
             };
 
@@ -448,17 +744,19 @@ impl Dnspod {
                 break;
             }
 
+            // records of types not managed are left untouched
             for old_record in &records {
-                ret.push(Arc::new((*old_record).clone()));
+                if self.sub_domain.manages(&old_record.r#type) {
+                    ret.push(Arc::new((*old_record).clone()));
+                }
             }
 
-            page_offset += records.len();
-            if page_offset % 100 != 0 {
+            if records.len() < 100 {
                 break;
             }
         }
 
-        ret
+        Some(ret)
     }
 
     async fn remove_records(
@@ -466,6 +764,10 @@ impl Dnspod {
         options: SharedProgramOptions,
         pending_to_delete: Vec<Arc<DnspodGetResponseRecord>>,
     ) -> i32 {
+        if self.api == DnspodApi::V3 {
+            return self.remove_records_v3(&options, pending_to_delete).await;
+        }
+
         let mut ret = 0;
         // Delete records no more need
         let delete_url = String::from("https://dnsapi.cn/Record.Remove");
@@ -514,6 +816,10 @@ impl Dnspod {
         options: SharedProgramOptions,
         pending_to_create: Vec<Arc<DnspodRecordAction>>,
     ) -> i32 {
+        if self.api == DnspodApi::V3 {
+            return self.create_records_v3(&options, pending_to_create).await;
+        }
+
         let mut ret = 0;
         let create_url = String::from("https://dnsapi.cn/Record.Create");
 
@@ -588,4 +894,450 @@ impl Dnspod {
             Some(String::from(res.get_error_message()))
         }
     }
+
+    /// Common parameters of Tencent Cloud API 3.0, domain id is preferred if it's set.
+    fn generate_common_payload(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut payload = serde_json::Map::new();
+        payload.insert(String::from("Domain"), self.domain.clone().into());
+        if let Ok(domain_id) = self.domain_id.parse::<u64>() {
+            payload.insert(String::from("DomainId"), domain_id.into());
+        }
+        payload
+    }
+
+    /// Send signed request of Tencent Cloud API 3.0 and return the content of Response.
+    async fn request_v3(
+        &self,
+        options: &SharedProgramOptions,
+        action: &str,
+        payload: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<serde_json::Value, DnspodV3Error> {
+        let parsed_url = reqwest::Url::parse(&self.endpoint).map_err(DnspodV3Error::new)?;
+        let host = match (parsed_url.host_str(), parsed_url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => return Err(DnspodV3Error::new(format!("no host in {}", self.endpoint))),
+        };
+        let payload = serde_json::Value::Object(payload).to_string();
+        let timestamp = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        let rsp = options
+            .http(HttpMethod::Post, parsed_url.as_str())
+            .header(CONTENT_TYPE, DNSPOD_V3_CONTENT_TYPE)
+            .header("X-TC-Action", action)
+            .header("X-TC-Version", DNSPOD_V3_VERSION)
+            .header("X-TC-Timestamp", timestamp.to_string())
+            .header(
+                "Authorization",
+                tc3_authorization(
+                    &self.secret_id,
+                    &self.secret_key,
+                    &host,
+                    &payload,
+                    timestamp,
+                ),
+            )
+            .body(payload)
+            .send()
+            .await
+            .map_err(DnspodV3Error::new)?;
+        let rsp_text = rsp.text().await.map_err(DnspodV3Error::new)?;
+
+        let mut rsp_value = serde_json::from_str::<serde_json::Value>(&rsp_text)
+            .map_err(|e| DnspodV3Error::new(format!("{}.\nbody: {}", e, rsp_text)))?;
+        let rsp_value = rsp_value
+            .get_mut("Response")
+            .map(serde_json::Value::take)
+            .ok_or_else(|| DnspodV3Error::new(format!("no Response in body: {}", rsp_text)))?;
+        match rsp_value.get("Error") {
+            Some(error) => Err(serde_json::from_value::<DnspodV3Error>(error.clone())
+                .unwrap_or_else(|_| DnspodV3Error::new(error.to_string()))),
+            None => Ok(rsp_value),
+        }
+    }
+
+    async fn get_records_v3(
+        &self,
+        options: &SharedProgramOptions,
+    ) -> Option<Vec<Arc<DnspodGetResponseRecord>>> {
+        let mut ret: Vec<Arc<DnspodGetResponseRecord>> = vec![];
+
+        let mut page_offset: usize = 0;
+        loop {
+            let mut payload = self.generate_common_payload();
+            payload.insert(
+                String::from("Subdomain"),
+                self.sub_domain.name.clone().into(),
+            );
+            payload.insert(String::from("Offset"), page_offset.into());
+            payload.insert(String::from("Limit"), DNSPOD_V3_PAGE_SIZE.into());
+
+            let records = match self
+                .request_v3(options, "DescribeRecordList", payload)
+                .await
+                .and_then(|v| {
+                    serde_json::from_value::<DnspodV3RecordList>(v).map_err(DnspodV3Error::new)
+                }) {
+                Ok(v) => v.record_list,
+                Err(e) if e.code == DNSPOD_V3_ERROR_NO_RECORD => vec![],
+                Err(e) => {
+                    if let Some(ref logger) = self.logger {
+                        error!(logger, "Describe record list failed, error: {}", e);
+                    }
+                    return None;
+                }
+            };
+
+            let records_count = records.len();
+            // records of types not managed are left untouched
+            for old_record in records {
+                if self.sub_domain.manages(&old_record.r#type) {
+                    ret.push(Arc::new(old_record.into()));
+                }
+            }
+
+            page_offset += records_count;
+            if records_count < DNSPOD_V3_PAGE_SIZE {
+                break;
+            }
+        }
+
+        Some(ret)
+    }
+
+    /// RecordId of Tencent Cloud API 3.0 is a number, invalid ones are logged and skipped.
+    fn record_id_v3(&self, record: &DnspodGetResponseRecord) -> Option<u64> {
+        match record.id.parse::<u64>() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                if let Some(ref logger) = self.logger {
+                    error!(
+                        logger,
+                        "Skip {} {} for {}, invalid record id {}, error: {}",
+                        record.name,
+                        record.value,
+                        self.domain,
+                        record.id,
+                        e
+                    );
+                }
+                None
+            }
+        }
+    }
+
+    /// Modify records pending to delete to values pending to create, modified ones are removed
+    /// from both.
+    async fn modify_records_v3(
+        &self,
+        options: &SharedProgramOptions,
+        pending_to_delete: &mut Vec<Arc<DnspodGetResponseRecord>>,
+        pending_to_create: &mut Vec<Arc<DnspodRecordAction>>,
+    ) -> i32 {
+        let mut ret = 0;
+        while !pending_to_delete.is_empty() && !pending_to_create.is_empty() {
+            let old_record = pending_to_delete.remove(0);
+            let record_id = match self.record_id_v3(&old_record) {
+                Some(v) => v,
+                None => {
+                    ret += 1;
+                    continue;
+                }
+            };
+            let new_record = pending_to_create.remove(0).record.clone();
+
+            let mut payload = self.generate_common_payload();
+            payload.insert(String::from("RecordId"), record_id.into());
+            payload.insert(
+                String::from("SubDomain"),
+                new_record.sub_domain.clone().into(),
+            );
+            payload.insert(String::from("RecordType"), new_record.record_type.into());
+            payload.insert(String::from("RecordLine"), DNSPOD_V3_RECORD_LINE.into());
+            payload.insert(
+                String::from("RecordLineId"),
+                new_record.record_line_id.clone().into(),
+            );
+            payload.insert(String::from("Value"), new_record.value.clone().into());
+
+            match self.request_v3(options, "ModifyRecord", payload).await {
+                Ok(_) => {
+                    if let Some(ref logger) = self.logger {
+                        debug!(
+                            logger,
+                            "Modify {} {} to {} for {} success.",
+                            old_record.name,
+                            old_record.value,
+                            new_record.value,
+                            self.domain
+                        );
+                    }
+                }
+                Err(e) => {
+                    ret += 1;
+                    if let Some(ref logger) = self.logger {
+                        error!(
+                            logger,
+                            "Modify {} {} to {} for {} failed, error: {}",
+                            old_record.name,
+                            old_record.value,
+                            new_record.value,
+                            self.domain,
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+
+    async fn remove_records_v3(
+        &self,
+        options: &SharedProgramOptions,
+        pending_to_delete: Vec<Arc<DnspodGetResponseRecord>>,
+    ) -> i32 {
+        let mut ret = 0;
+        for old_record in pending_to_delete {
+            let record_id = match self.record_id_v3(&old_record) {
+                Some(v) => v,
+                None => {
+                    ret += 1;
+                    continue;
+                }
+            };
+
+            let mut payload = self.generate_common_payload();
+            payload.insert(String::from("RecordId"), record_id.into());
+
+            match self.request_v3(options, "DeleteRecord", payload).await {
+                Ok(_) => {
+                    if let Some(ref logger) = self.logger {
+                        debug!(
+                            logger,
+                            "Delete {} for {} success.", old_record.name, self.domain
+                        );
+                    }
+                }
+                Err(e) => {
+                    ret += 1;
+                    if let Some(ref logger) = self.logger {
+                        error!(
+                            logger,
+                            "Delete {} for {} failed, error: {}", old_record.name, self.domain, e
+                        );
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+
+    async fn create_records_v3(
+        &self,
+        options: &SharedProgramOptions,
+        pending_to_create: Vec<Arc<DnspodRecordAction>>,
+    ) -> i32 {
+        let mut ret = 0;
+        for new_record_action in pending_to_create {
+            let new_record = &new_record_action.record;
+            let mut payload = self.generate_common_payload();
+            payload.insert(
+                String::from("SubDomain"),
+                new_record.sub_domain.clone().into(),
+            );
+            payload.insert(String::from("RecordType"), new_record.record_type.into());
+            payload.insert(String::from("RecordLine"), DNSPOD_V3_RECORD_LINE.into());
+            payload.insert(
+                String::from("RecordLineId"),
+                new_record.record_line_id.clone().into(),
+            );
+            payload.insert(String::from("Value"), new_record.value.clone().into());
+
+            match self.request_v3(options, "CreateRecord", payload).await {
+                Ok(_) => {
+                    if let Some(ref logger) = self.logger {
+                        debug!(
+                            logger,
+                            "Create {} for {} success.", new_record.sub_domain, self.domain
+                        );
+                    }
+                }
+                Err(e) => {
+                    ret += 1;
+                    if let Some(ref logger) = self.logger {
+                        error!(
+                            logger,
+                            "Create {} for {} failed, error: {}",
+                            new_record.sub_domain,
+                            self.domain,
+                            e
+                        );
+                    }
+                }
+            }
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::time::Duration;
+
+    #[test]
+    fn test_tc3_authorization() {
+        assert_eq!(
+            tc3_authorization(
+                "testid",
+                "testsecret",
+                "dnspod.tencentcloudapi.com",
+                r#"{"Domain":"example.com","Subdomain":"home"}"#,
+                1551113065
+            ),
+            "TC3-HMAC-SHA256 Credential=testid/2019-02-25/dnspod/tc3_request, SignedHeaders=content-type;host, Signature=40bb362a9136a0c8b711907be23735ecbdb29fcd9971dae1e955247b221c8254"
+        );
+        assert_eq!("V3".parse::<DnspodApi>(), Ok(DnspodApi::V3));
+        assert!("v2".parse::<DnspodApi>().is_err());
+    }
+
+    #[test]
+    fn test_update_v3() {
+        const NO_RECORD: &str = r#"{"Response":{"Error":{"Code":"ResourceNotFound.NoDataOfRecord","Message":"记录列表为空。"},"RequestId":"R1"}}"#;
+
+        async_std::task::block_on(async {
+            let (address, server) = test_util::http_stand_in(vec![
+                (200, String::from(NO_RECORD)),
+                (200, String::from(NO_RECORD)),
+                (
+                    200,
+                    String::from(r#"{"Response":{"RecordId":12,"RequestId":"R3"}}"#),
+                ),
+                (
+                    200,
+                    String::from(
+                        r#"{"Response":{"Error":{"Code":"RequestLimitExceeded","Message":"请求的次数超过了频率限制。"},"RequestId":"R4"}}"#,
+                    ),
+                ),
+            ])
+            .await;
+
+            let options = test_util::default_options(Duration::from_secs(3));
+            let mut driver = Dnspod {
+                api: DnspodApi::V3,
+                domain_id: String::from("1234"),
+                domain: String::from("example.com"),
+                secret_id: String::from("testid"),
+                secret_key: String::from("testsecret"),
+                endpoint: format!("http://{}", address),
+                sub_domain: "@".parse().unwrap(),
+                logger: Some(slog::Logger::root(slog::Discard, o!())),
+                ..Default::default()
+            };
+            let recs = vec![Record::Aaaa("2001:db8::1".parse().unwrap())];
+
+            // Empty record list is not an error
+            let plans = driver.plan_update(options.clone(), &recs).await.unwrap();
+            assert_eq!(plans[0].domain, "example.com");
+            let changes: Vec<(PlanAction, &str, &str)> = plans[0]
+                .changes
+                .iter()
+                .map(|c| (c.action, c.r#type.as_str(), c.content.as_str()))
+                .collect();
+            assert_eq!(changes, vec![(PlanAction::Create, "AAAA", "2001:db8::1")]);
+            assert_eq!(driver.update(options.clone(), &recs).await, Ok(0));
+            // Nothing is created if old records can not be listed
+            assert_eq!(driver.update(options, &recs).await, Err(()));
+
+            let requests = server.await;
+            assert_eq!(requests.len(), 4);
+            let headers = requests[1].to_ascii_lowercase();
+            assert!(headers.contains("x-tc-action: describerecordlist\r\n"));
+            assert!(headers.contains("x-tc-version: 2021-03-23\r\n"));
+            assert!(headers.contains("authorization: tc3-hmac-sha256 credential=testid/"));
+            assert!(requests[1].contains(r#""DomainId":1234"#));
+            assert!(requests[1].contains(r#""Subdomain":"@""#));
+
+            assert!(requests[2]
+                .to_ascii_lowercase()
+                .contains("x-tc-action: createrecord\r\n"));
+            for field in [
+                r#""Domain":"example.com""#,
+                r#""SubDomain":"@""#,
+                r#""RecordType":"AAAA""#,
+                r#""RecordLine":"默认""#,
+                r#""Value":"2001:db8::1""#,
+            ] {
+                assert!(requests[2].contains(field), "{} not found", field);
+            }
+            assert!(requests[3]
+                .to_ascii_lowercase()
+                .contains("x-tc-action: describerecordlist\r\n"));
+        });
+    }
+
+    #[test]
+    fn test_invalid_record_id_v3() {
+        async_std::task::block_on(async {
+            let (address, server) = test_util::http_stand_in(vec![(
+                200,
+                String::from(r#"{"Response":{"RequestId":"R1"}}"#),
+            )])
+            .await;
+
+            let options = test_util::default_options(Duration::from_secs(3));
+            let driver = Dnspod {
+                api: DnspodApi::V3,
+                domain: String::from("example.com"),
+                secret_id: String::from("testid"),
+                secret_key: String::from("testsecret"),
+                endpoint: format!("http://{}", address),
+                logger: Some(slog::Logger::root(slog::Discard, o!())),
+                ..Default::default()
+            };
+            let old_record = |id: &str, value: &str| {
+                Arc::new(DnspodGetResponseRecord {
+                    id: String::from(id),
+                    name: String::from("home"),
+                    line: String::from("默认"),
+                    line_id: String::from("0"),
+                    r#type: String::from("A"),
+                    value: String::from(value),
+                    mx: String::from("0"),
+                    ttl: String::from("600"),
+                })
+            };
+
+            // Record with invalid id is skipped as a failure, the next one is modified instead
+            let mut pending_to_delete =
+                vec![old_record("", "192.0.2.8"), old_record("7", "192.0.2.9")];
+            let mut pending_to_create =
+                create_actions(&[Record::A(std::net::Ipv4Addr::new(192, 0, 2, 1))]);
+            assert_eq!(
+                driver
+                    .modify_records_v3(&options, &mut pending_to_delete, &mut pending_to_create)
+                    .await,
+                1
+            );
+            assert!(pending_to_delete.is_empty() && pending_to_create.is_empty());
+            assert_eq!(
+                driver
+                    .remove_records_v3(&options, vec![old_record("x7", "192.0.2.10")])
+                    .await,
+                1
+            );
+
+            let requests = server.await;
+            assert!(requests[0]
+                .to_ascii_lowercase()
+                .contains("x-tc-action: modifyrecord\r\n"));
+            assert!(requests[0].contains(r#""RecordId":7"#));
+            assert!(requests[0].contains(r#""Value":"192.0.2.1""#));
+        });
+    }
 }
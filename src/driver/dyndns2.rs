@@ -0,0 +1,440 @@
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use futures::future::{self, BoxFuture, FutureExt};
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::option;
+use super::{DomainPlan, Driver, DriverResult, ManagedDomain, PlanAction, PlanResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+type HttpMethod = super::HttpMethod;
+
+/// Clients must wait at least 30 minutes after 911 or dnserr.
+const DYNDNS2_SERVER_ERROR_BACKOFF: Duration = Duration::from_secs(30 * 60);
+
+/// Result of an update request, @see https://help.dyn.com/remote-access-api/return-codes/
+#[derive(Debug, Clone, PartialEq)]
+enum Dyndns2Status {
+    /// good or nochg
+    Success(String),
+    /// Problems of configuration or abuse, clients must not retry before they are fixed
+    Fatal(String),
+    /// 911 or dnserr, clients must not retry before DYNDNS2_SERVER_ERROR_BACKOFF
+    ServerError(String),
+    /// Anything unknown, which can be retried later
+    Retry(String),
+}
+
+fn parse_status(body: &str) -> Dyndns2Status {
+    let line = body.lines().map(str::trim).find(|l| !l.is_empty());
+    let line = line.unwrap_or_default().to_string();
+    match line.split_whitespace().next().unwrap_or_default() {
+        "good" | "nochg" => Dyndns2Status::Success(line),
+        "badauth" | "!donator" | "notfqdn" | "nohost" | "numhost" | "abuse" | "badagent" => {
+            Dyndns2Status::Fatal(line)
+        }
+        "911" | "dnserr" => Dyndns2Status::ServerError(line),
+        _ => Dyndns2Status::Retry(line),
+    }
+}
+
+/// Value of myip, A and AAAA records are joined by comma.
+fn my_ip(recs: &[Record]) -> String {
+    let addresses: Vec<String> = recs
+        .iter()
+        .filter_map(|r| match r {
+            Record::A(v) => Some(v.to_string()),
+            Record::Aaaa(v) => Some(v.to_string()),
+            _ => None,
+        })
+        .collect();
+    addresses.join(",")
+}
+
+/// dyndns2 only replaces addresses of families in myip, others are kept.
+fn plan_changes(hostname: &str, current: &[IpAddr], recs: &[Record]) -> DomainPlan {
+    let mut plan = DomainPlan::new("dyndns2", hostname.to_string());
+    let new_values: Vec<(&'static str, String)> = recs
+        .iter()
+        .filter_map(|r| match r {
+            Record::A(v) => Some(("A", v.to_string())),
+            Record::Aaaa(v) => Some(("AAAA", v.to_string())),
+            _ => None,
+        })
+        .collect();
+
+    for address in current {
+        let r#type = if address.is_ipv4() { "A" } else { "AAAA" };
+        let value = address.to_string();
+        let action = if new_values.iter().all(|(t, _)| *t != r#type)
+            || new_values.iter().any(|(t, v)| *t == r#type && *v == value)
+        {
+            PlanAction::Keep
+        } else {
+            PlanAction::Delete
+        };
+        plan.add(action, r#type, value);
+    }
+
+    for (r#type, value) in new_values {
+        if !current.iter().any(|address| address.to_string() == value) {
+            plan.add(PlanAction::Create, r#type, value);
+        }
+    }
+    plan
+}
+
+#[derive(Default)]
+pub struct Dyndns2 {
+    server: String,
+    username: String,
+    password: String,
+    hostnames: Vec<ManagedDomain>,
+    /// Response code which requires user's intervention, no more request will be sent
+    fatal_error: Option<String>,
+    /// No request will be sent before it after a server error
+    retry_after: Option<Instant>,
+    logger: Option<slog::Logger>,
+}
+
+impl Driver for Dyndns2 {
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("dyndns2-server")
+                .long("dyndns2-server")
+                .value_name("URL")
+                .help("Set update URL of dyndns2 protocol, such as https://dynupdate.no-ip.com/nic/update"),
+        ).arg(
+            Arg::new("dyndns2-username")
+                .long("dyndns2-username")
+                .value_name("USERNAME")
+                .help("Set username of basic authentication for dyndns2 protocol"),
+        ).arg(
+            Arg::new("dyndns2-password")
+                .long("dyndns2-password")
+                .value_name("PASSWORD")
+                .help("Set password of basic authentication for dyndns2 protocol"),
+        ).arg(
+            Arg::new("dyndns2-hostname")
+                .long("dyndns2-hostname")
+                .value_name("HOSTNAME")
+                .action(ArgAction::Append)
+                .help("Add hostname to update by dyndns2 protocol, HOSTNAME:A or HOSTNAME:AAAA to only send addresses of these types"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        self.server = option::unwraper_option_or(matches, "dyndns2-server", String::default());
+        self.username = option::unwraper_option_or(matches, "dyndns2-username", String::default());
+        self.password = option::unwraper_option_or(matches, "dyndns2-password", String::default());
+        if self.server.is_empty() {
+            return;
+        }
+
+        let logger = options.create_logger("Dyndns2");
+        self.hostnames =
+            option::unwraper_multiple_values(matches, "dyndns2-hostname", &logger, "hostname");
+        if !self.hostnames.is_empty() {
+            self.logger = Some(logger);
+        }
+    }
+
+    fn state_keys(&self) -> Vec<String> {
+        if self.logger.is_none() {
+            return vec![];
+        }
+
+        self.hostnames
+            .iter()
+            .map(|hostname| format!("dyndns2:{}:{}", self.server, hostname))
+            .collect()
+    }
+
+    fn run<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, DriverResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() {
+            return future::ready(Ok(0)).boxed();
+        }
+
+        self.update(options.clone(), recs).boxed()
+    }
+
+    fn plan<'a, 'b, 'c>(
+        &'a mut self,
+        _options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, PlanResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() {
+            return future::ready(Ok(vec![])).boxed();
+        }
+
+        self.plan_update(recs).boxed()
+    }
+}
+
+impl Dyndns2 {
+    fn logger(&self) -> &slog::Logger {
+        self.logger.as_ref().unwrap()
+    }
+
+    /// dyndns2 has no API to list records, current addresses are resolved by DNS.
+    async fn plan_update(&mut self, recs: &[Record]) -> PlanResult {
+        let mut ret = vec![];
+        for hostname in &self.hostnames {
+            let mut current: Vec<IpAddr> =
+                match async_std::net::ToSocketAddrs::to_socket_addrs(&(hostname.name.as_str(), 0))
+                    .await
+                {
+                    Ok(addresses) => addresses
+                        .map(|a| a.ip())
+                        .filter(|a| hostname.manages(if a.is_ipv4() { "A" } else { "AAAA" }))
+                        .collect(),
+                    Err(e) => {
+                        debug!(
+                            self.logger(),
+                            "Resolve {} failed, error: {}", hostname.name, e
+                        );
+                        vec![]
+                    }
+                };
+            current.sort();
+            current.dedup();
+            ret.push(plan_changes(
+                &hostname.name,
+                &current,
+                &hostname.select(recs),
+            ));
+        }
+        Ok(ret)
+    }
+
+    async fn update(&mut self, options: SharedProgramOptions, recs: &[Record]) -> DriverResult {
+        if let Some(ref code) = self.fatal_error {
+            error!(
+                self.logger(),
+                "Skip updating by {} because of {} before, please check the options and restart",
+                self.server,
+                code
+            );
+            return Err(());
+        }
+        if let Some(retry_after) = self.retry_after {
+            let now = Instant::now();
+            if now < retry_after {
+                error!(
+                    self.logger(),
+                    "Skip updating by {} because of server error before, retry after {}s",
+                    self.server,
+                    (retry_after - now).as_secs()
+                );
+                return Err(());
+            }
+            self.retry_after = None;
+        }
+
+        let mut failed_count: i32 = 0;
+        for hostname in &self.hostnames {
+            let myip = my_ip(&hostname.select(recs));
+            if myip.is_empty() {
+                // Server will use address of this request if myip is not set
+                info!(
+                    self.logger(),
+                    "Skip updating {}, no address to update", hostname.name
+                );
+                continue;
+            }
+
+            let mut cli = options
+                .http(HttpMethod::Get, &self.server)
+                .query(&[("hostname", hostname.name.as_str()), ("myip", &myip)]);
+            if !self.username.is_empty() || !self.password.is_empty() {
+                cli = cli.basic_auth(&self.username, Some(&self.password));
+            }
+            let rsp_text = match cli.send().await {
+                Ok(rsp) => {
+                    let status = rsp.status();
+                    match rsp.text().await {
+                        Ok(v) if v.trim().is_empty() => format!("HTTP {}", status),
+                        Ok(v) => v,
+                        Err(e) => format!("HTTP {}, {}", status, e),
+                    }
+                }
+                Err(e) => e.to_string(),
+            };
+
+            match parse_status(&rsp_text) {
+                Dyndns2Status::Success(v) => {
+                    info!(
+                        self.logger(),
+                        "Update {} to {} finished, {}", hostname.name, myip, v
+                    );
+                }
+                Dyndns2Status::Fatal(v) => {
+                    error!(
+                        self.logger(),
+                        "Update {} to {} failed, {}, no more request will be sent",
+                        hostname.name,
+                        myip,
+                        v
+                    );
+                    self.fatal_error = Some(v);
+                    return Err(());
+                }
+                Dyndns2Status::ServerError(v) => {
+                    error!(
+                        self.logger(),
+                        "Update {} to {} failed, {}, no request will be sent in {}s",
+                        hostname.name,
+                        myip,
+                        v,
+                        DYNDNS2_SERVER_ERROR_BACKOFF.as_secs()
+                    );
+                    self.retry_after = Some(Instant::now() + DYNDNS2_SERVER_ERROR_BACKOFF);
+                    return Err(());
+                }
+                Dyndns2Status::Retry(v) => {
+                    failed_count += 1;
+                    error!(
+                        self.logger(),
+                        "Update {} to {} failed, {}", hostname.name, myip, v
+                    );
+                }
+            }
+        }
+
+        if failed_count > 0 {
+            Err(())
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(
+            parse_status("good 192.0.2.1\n"),
+            Dyndns2Status::Success(String::from("good 192.0.2.1"))
+        );
+        assert_eq!(
+            parse_status("\r\nnochg 192.0.2.1"),
+            Dyndns2Status::Success(String::from("nochg 192.0.2.1"))
+        );
+        assert_eq!(
+            parse_status("badauth"),
+            Dyndns2Status::Fatal(String::from("badauth"))
+        );
+        assert_eq!(
+            parse_status("abuse"),
+            Dyndns2Status::Fatal(String::from("abuse"))
+        );
+        assert_eq!(
+            parse_status("911"),
+            Dyndns2Status::ServerError(String::from("911"))
+        );
+        assert_eq!(
+            parse_status("dnserr\n"),
+            Dyndns2Status::ServerError(String::from("dnserr"))
+        );
+        assert_eq!(
+            parse_status("<html>Bad Gateway</html>"),
+            Dyndns2Status::Retry(String::from("<html>Bad Gateway</html>"))
+        );
+        assert_eq!(parse_status(""), Dyndns2Status::Retry(String::new()));
+    }
+
+    #[test]
+    fn test_plan_changes() {
+        let recs = vec![Record::A(Ipv4Addr::new(192, 0, 2, 1))];
+        let current: Vec<IpAddr> =
+            vec!["192.0.2.9".parse().unwrap(), "2001:db8::1".parse().unwrap()];
+        let plan = plan_changes("home.example.com", &current, &recs);
+        let changes: Vec<(PlanAction, &str, &str)> = plan
+            .changes
+            .iter()
+            .map(|c| (c.action, c.r#type.as_str(), c.content.as_str()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (PlanAction::Delete, "A", "192.0.2.9"),
+                (PlanAction::Keep, "AAAA", "2001:db8::1"),
+                (PlanAction::Create, "A", "192.0.2.1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update() {
+        async_std::task::block_on(async {
+            let (address, server) = test_util::http_stand_in(vec![
+                (200, String::from("nochg 192.0.2.1")),
+                (200, String::from("dnserr")),
+                (401, String::from("badauth")),
+            ])
+            .await;
+
+            let options = test_util::default_options(Duration::from_secs(3));
+            let mut driver = Dyndns2 {
+                server: format!("http://{}/nic/update", address),
+                username: String::from("user"),
+                password: String::from("pass"),
+                hostnames: vec![
+                    "home.example.com".parse().unwrap(),
+                    "v6.example.com:AAAA".parse().unwrap(),
+                    "v4.example.com:A".parse().unwrap(),
+                ],
+                logger: Some(slog::Logger::root(slog::Discard, o!())),
+                ..Default::default()
+            };
+            let recs = vec![Record::A(Ipv4Addr::new(192, 0, 2, 1))];
+
+            // v6.example.com has no address to send, dnserr of v4.example.com starts backoff
+            assert_eq!(driver.update(options.clone(), &recs).await, Err(()));
+            assert_eq!(driver.fatal_error, None);
+            let retry_after = driver.retry_after.unwrap();
+            assert!(retry_after >= Instant::now() + Duration::from_secs(29 * 60));
+            // No request inside the backoff window
+            assert_eq!(driver.update(options.clone(), &recs).await, Err(()));
+            assert_eq!(driver.retry_after, Some(retry_after));
+
+            driver.retry_after = Some(Instant::now());
+            assert_eq!(driver.update(options.clone(), &recs).await, Err(()));
+            assert_eq!(driver.retry_after, None);
+            assert_eq!(driver.fatal_error, Some(String::from("badauth")));
+            // No more request after badauth
+            assert_eq!(driver.update(options, &recs).await, Err(()));
+
+            let requests = server.await;
+            assert_eq!(requests.len(), 3);
+            assert!(requests[0]
+                .starts_with("GET /nic/update?hostname=home.example.com&myip=192.0.2.1 "));
+            // base64 of user:pass
+            assert!(requests[0].contains("dXNlcjpwYXNz"));
+            assert!(
+                requests[1].starts_with("GET /nic/update?hostname=v4.example.com&myip=192.0.2.1 ")
+            );
+            assert!(requests[2].starts_with("GET /nic/update?hostname=home.example.com&myip="));
+        });
+    }
+}
@@ -1,5 +1,10 @@
+use std::fmt;
+use std::str::FromStr;
+
 use futures::future::BoxFuture;
 
+use serde::Serialize;
+
 extern crate clap;
 use clap::{ArgMatches, Command};
 
@@ -7,19 +12,153 @@ use super::detector;
 
 pub type Record = detector::Record;
 pub type DriverResult = Result<i32, ()>;
+pub type PlanResult = Result<Vec<DomainPlan>, ()>;
 
+mod aliyun;
 mod cloudflare;
 mod dnspod;
+mod dyndns2;
+mod rfc2136;
+mod route53;
+mod sign;
 
+pub type Aliyun = aliyun::Aliyun;
 pub type Cloudflare = cloudflare::Cloudflare;
 pub type Dnspod = dnspod::Dnspod;
+pub type Dyndns2 = dyndns2::Dyndns2;
+pub type Rfc2136 = rfc2136::Rfc2136;
+pub type Route53 = route53::Route53;
 pub type SharedProgramOptions = super::option::SharedProgramOptions;
 pub type HttpMethod = super::option::HttpMethod;
 
+/// A domain and record types managed by drivers, parsed from DOMAIN or DOMAIN:A,AAAA.
+/// Records of other types are left untouched, all types are managed if not set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ManagedDomain {
+    pub name: String,
+    pub types: Vec<&'static str>,
+}
+
+impl ManagedDomain {
+    pub fn manages(&self, r#type: &str) -> bool {
+        self.types.is_empty() || self.types.iter().any(|t| t.eq_ignore_ascii_case(r#type))
+    }
+
+    /// Records of managed types.
+    pub fn select(&self, recs: &[Record]) -> Vec<Record> {
+        recs.iter()
+            .filter(|r| self.manages(r.type_name()))
+            .cloned()
+            .collect()
+    }
+}
+
+impl fmt::Display for ManagedDomain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.types.is_empty() {
+            f.write_str(&self.name)
+        } else {
+            f.write_fmt(format_args!("{}:{}", self.name, self.types.join(",")))
+        }
+    }
+}
+
+impl FromStr for ManagedDomain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, types) = match s.split_once(':') {
+            Some((name, types)) => (name, Some(types)),
+            None => (s, None),
+        };
+        if name.is_empty() {
+            return Err(format!("empty domain in {}", s));
+        }
+
+        let mut ret = ManagedDomain {
+            name: name.to_string(),
+            types: vec![],
+        };
+        for t in types.unwrap_or_default().split(',') {
+            let t = match t.trim().to_ascii_uppercase().as_str() {
+                "" => continue,
+                "A" => "A",
+                "AAAA" => "AAAA",
+                _ => return Err(format!("unsupported record type {} in {}", t, s)),
+            };
+            if !ret.types.contains(&t) {
+                ret.types.push(t);
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Create,
+    Delete,
+    Keep,
+}
+
+impl fmt::Display for PlanAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PlanAction::Create => f.write_str("create"),
+            PlanAction::Delete => f.write_str("delete"),
+            PlanAction::Keep => f.write_str("keep"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanChange {
+    pub action: PlanAction,
+    pub r#type: String,
+    pub content: String,
+}
+
+/// Changes of one domain which will be applied by a driver.
+#[derive(Debug, Clone, Serialize)]
+pub struct DomainPlan {
+    pub profile: String,
+    pub driver: &'static str,
+    pub domain: String,
+    pub changes: Vec<PlanChange>,
+}
+
+impl DomainPlan {
+    pub fn new(driver: &'static str, domain: String) -> Self {
+        DomainPlan {
+            profile: String::default(),
+            driver,
+            domain,
+            changes: vec![],
+        }
+    }
+
+    pub fn add<T, C>(&mut self, action: PlanAction, r#type: T, content: C)
+    where
+        T: ToString,
+        C: ToString,
+    {
+        self.changes.push(PlanChange {
+            action,
+            r#type: r#type.to_string(),
+            content: content.to_string(),
+        });
+    }
+}
+
 pub trait Driver {
     fn initialize(&mut self, app: Command) -> Command;
     fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions);
 
+    /// Keys of driver and domain to save published records in state file.
+    /// The driver is always run when it's empty.
+    fn state_keys(&self) -> Vec<String>;
+
     fn run<'a, 'b, 'c>(
         &'a mut self,
         options: &SharedProgramOptions,
@@ -28,4 +167,66 @@ pub trait Driver {
     where
         'a: 'b,
         'c: 'a;
+
+    /// Fetch current records and report changes without modifying anything.
+    fn plan<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, PlanResult>
+    where
+        'a: 'b,
+        'c: 'a;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_managed_domain() {
+        let domain: ManagedDomain = "home.example.com".parse().unwrap();
+        assert_eq!(domain.name, "home.example.com");
+        assert!(domain.manages("A") && domain.manages("TXT"));
+
+        let domain: ManagedDomain = "vpn.example.com:a, AAAA,A".parse().unwrap();
+        assert_eq!(domain.name, "vpn.example.com");
+        assert_eq!(domain.types, vec!["A", "AAAA"]);
+        assert_eq!(domain.to_string(), "vpn.example.com:A,AAAA");
+
+        let domain: ManagedDomain = "home.example.com:AAAA".parse().unwrap();
+        assert!(!domain.manages("A") && domain.manages("aaaa") && !domain.manages("TXT"));
+        let recs = vec![
+            Record::A(Ipv4Addr::new(192, 0, 2, 1)),
+            Record::Aaaa(Ipv6Addr::LOCALHOST),
+        ];
+        assert_eq!(
+            domain.select(&recs),
+            vec![Record::Aaaa(Ipv6Addr::LOCALHOST)]
+        );
+
+        assert!("home.example.com:MX".parse::<ManagedDomain>().is_err());
+        assert!(":A".parse::<ManagedDomain>().is_err());
+    }
+
+    #[test]
+    fn test_plan_json() {
+        let mut plan = DomainPlan::new("cloudflare", String::from("home.example.com"));
+        plan.profile = String::from("home");
+        plan.add(PlanAction::Keep, "A", Ipv4Addr::new(192, 0, 2, 1));
+        plan.add(PlanAction::Delete, "AAAA", Ipv6Addr::LOCALHOST);
+        plan.add(PlanAction::Create, "AAAA", "2001:db8::1");
+
+        // Scripts depend on the field names and lowercase actions
+        assert_eq!(
+            serde_json::to_string(&vec![&plan]).unwrap(),
+            concat!(
+                r#"[{"profile":"home","driver":"cloudflare","domain":"home.example.com","changes":["#,
+                r#"{"action":"keep","type":"A","content":"192.0.2.1"},"#,
+                r#"{"action":"delete","type":"AAAA","content":"::1"},"#,
+                r#"{"action":"create","type":"AAAA","content":"2001:db8::1"}]}]"#
+            )
+        );
+    }
 }
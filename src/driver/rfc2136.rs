@@ -0,0 +1,639 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_std::net::ToSocketAddrs;
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use super::super::daemon;
+use super::super::dns;
+use super::super::option;
+use super::{DomainPlan, Driver, DriverResult, ManagedDomain, PlanAction, PlanResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+
+const DNS_DEFAULT_PORT: u16 = 53;
+
+/// Dynamic update(RFC 2136) of zones on self-hosted DNS servers, signed by TSIG.
+#[derive(Default)]
+pub struct Rfc2136 {
+    server: String,
+    zone: String,
+    domains: Vec<ManagedDomain>,
+    key: Option<dns::TsigKey>,
+    ttl: u32,
+    tcp: bool,
+    logger: Option<slog::Logger>,
+}
+
+/// Only A and AAAA records are managed by dynamic update.
+fn managed_types(domain: &ManagedDomain) -> Vec<u16> {
+    [dns::TYPE_A, dns::TYPE_AAAA]
+        .into_iter()
+        .filter(|t| domain.manages(if *t == dns::TYPE_A { "A" } else { "AAAA" }))
+        .collect()
+}
+
+fn type_name(rtype: u16) -> &'static str {
+    if rtype == dns::TYPE_A {
+        "A"
+    } else {
+        "AAAA"
+    }
+}
+
+/// A and AAAA records of managed types of domain.
+fn resource_records(domain: &ManagedDomain, recs: &[Record], ttl: u32) -> Vec<dns::ResourceRecord> {
+    let types = managed_types(domain);
+    recs.iter()
+        .filter_map(|record| match record {
+            Record::A(v) => Some((dns::TYPE_A, v.octets().to_vec())),
+            Record::Aaaa(v) => Some((dns::TYPE_AAAA, v.octets().to_vec())),
+            _ => None,
+        })
+        .filter(|(rtype, _)| types.contains(rtype))
+        .map(|(rtype, data)| dns::ResourceRecord {
+            name: domain.name.clone(),
+            rtype,
+            rclass: dns::CLASS_IN,
+            ttl,
+            data,
+        })
+        .collect()
+}
+
+impl Driver for Rfc2136 {
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("rfc2136-server")
+                .long("rfc2136-server")
+                .value_name("HOST[:PORT]")
+                .help("Set primary DNS server to send dynamic update(RFC 2136) to, port 53 is used if it's not set"),
+        ).arg(
+            Arg::new("rfc2136-zone")
+                .long("rfc2136-zone")
+                .value_name("ZONE")
+                .help("Set zone to update by dynamic update"),
+        ).arg(
+            Arg::new("rfc2136-domain")
+                .long("rfc2136-domain")
+                .value_name("DOMAIN")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Add domain to update by dynamic update, DOMAIN:A or DOMAIN:AAAA to only update records of these types"),
+        ).arg(
+            Arg::new("rfc2136-key-name")
+                .long("rfc2136-key-name")
+                .value_name("NAME")
+                .requires("rfc2136-key-secret")
+                .help("Set name of TSIG key to sign dynamic update"),
+        ).arg(
+            Arg::new("rfc2136-key-secret")
+                .long("rfc2136-key-secret")
+                .value_name("BASE64 SECRET")
+                .requires("rfc2136-key-name")
+                .help("Set secret of TSIG key encoded by base64, which is the secret in key file of BIND or Knot"),
+        ).arg(
+            Arg::new("rfc2136-key-algorithm")
+                .long("rfc2136-key-algorithm")
+                .value_name("ALGORITHM")
+                .value_parser(["hmac-sha256", "hmac-sha512"])
+                .default_value("hmac-sha256")
+                .help("Set algorithm of TSIG key"),
+        ).arg(
+            Arg::new("rfc2136-ttl")
+                .long("rfc2136-ttl")
+                .value_name("SECONDS")
+                .default_value("300")
+                .help("Set TTL of records created by dynamic update"),
+        ).arg(
+            Arg::new("rfc2136-tcp")
+                .long("rfc2136-tcp")
+                .action(ArgAction::SetTrue)
+                .help("Send dynamic update by TCP, UDP is used and retried by TCP when the response is truncated by default"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        self.server = option::unwraper_option_or(matches, "rfc2136-server", String::default());
+        self.zone = option::unwraper_option_or(matches, "rfc2136-zone", String::default());
+        self.ttl = option::unwraper_from_str_or(matches, "rfc2136-ttl", 300);
+        self.tcp = option::unwraper_flag(matches, "rfc2136-tcp");
+        if self.server.is_empty() || self.zone.is_empty() {
+            return;
+        }
+
+        let logger = options.create_logger("Rfc2136");
+        let key_name = option::unwraper_option_or(matches, "rfc2136-key-name", String::default());
+        let key_secret =
+            option::unwraper_option_or(matches, "rfc2136-key-secret", String::default());
+        if !key_name.is_empty() {
+            let algorithm: dns::TsigAlgorithm =
+                option::unwraper_from_str_or(matches, "rfc2136-key-algorithm", Default::default());
+            match dns::TsigKey::new(&key_name, algorithm, &key_secret) {
+                Ok(key) => self.key = Some(key),
+                Err(e) => {
+                    error!(logger, "Invalid TSIG key {}, {}", key_name, e);
+                    return;
+                }
+            }
+        }
+
+        self.domains =
+            option::unwraper_multiple_values(matches, "rfc2136-domain", &logger, "domain");
+        self.logger = Some(logger);
+    }
+
+    fn state_keys(&self) -> Vec<String> {
+        if self.logger.is_none() {
+            return vec![];
+        }
+
+        self.domains
+            .iter()
+            .map(|domain| format!("rfc2136:{}:{}", self.server, domain))
+            .collect()
+    }
+
+    fn run<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, DriverResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() || self.domains.is_empty() {
+            return future::ready(Ok(0)).boxed();
+        }
+
+        self.update(options.clone(), recs).boxed()
+    }
+
+    fn plan<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, PlanResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() || self.domains.is_empty() {
+            return future::ready(Ok(vec![])).boxed();
+        }
+
+        self.plan_update(options.clone(), recs).boxed()
+    }
+}
+
+impl Rfc2136 {
+    fn logger(&self) -> &slog::Logger {
+        self.logger.as_ref().unwrap()
+    }
+
+    async fn resolve_server(&self) -> Result<SocketAddr, ()> {
+        let (host, port) = option::split_host_port(&self.server, DNS_DEFAULT_PORT);
+        match (host.as_str(), port).to_socket_addrs().await {
+            Ok(mut addrs) => match addrs.next() {
+                Some(addr) => Ok(addr),
+                None => {
+                    error!(self.logger(), "No address of DNS server {}", self.server);
+                    Err(())
+                }
+            },
+            Err(e) => {
+                error!(
+                    self.logger(),
+                    "Resolve DNS server {} failed, error: {}", self.server, e
+                );
+                Err(())
+            }
+        }
+    }
+
+    /// Response and its raw data, which is required to verify TSIG.
+    async fn exchange(
+        &self,
+        options: &SharedProgramOptions,
+        server: SocketAddr,
+        request: &[u8],
+    ) -> std::io::Result<(dns::Message, Vec<u8>)> {
+        let data = dns::exchange_data(server, request, options.timeout, self.tcp).await?;
+        let response = dns::Message::decode(&data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        Ok((response, data))
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    /// Delete RRsets of managed types and add new records, for all domains in one message.
+    fn build_update(&self, id: u16, recs: &[Record]) -> dns::Message {
+        let mut ret = dns::Message::update(id, &self.zone);
+        for domain in &self.domains {
+            for rtype in managed_types(domain) {
+                ret.authorities.push(dns::ResourceRecord {
+                    name: domain.name.clone(),
+                    rtype,
+                    rclass: dns::CLASS_ANY,
+                    ttl: 0,
+                    data: vec![],
+                });
+            }
+            ret.authorities
+                .extend(resource_records(domain, recs, self.ttl));
+        }
+        ret
+    }
+
+    async fn update(&mut self, options: SharedProgramOptions, recs: &[Record]) -> DriverResult {
+        let logger = self.logger();
+        let server = self.resolve_server().await?;
+
+        let message = self.build_update(daemon::random_u64() as u16, recs);
+        let request = match self.key {
+            Some(ref key) => key.sign(&message, Self::now()),
+            None => message.encode(),
+        };
+        let request = match request {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    logger,
+                    "Encode dynamic update of {} failed, error: {}", self.zone, e
+                );
+                return Err(());
+            }
+        };
+
+        debug!(logger, "Pending to update:");
+        for log_item in &message.authorities {
+            debug!(logger, "     -- {:?}", log_item);
+        }
+        let (response, response_data) = match self.exchange(&options, server, &request).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    logger,
+                    "Send dynamic update to {}({}) failed, error: {}", self.server, server, e
+                );
+                return Err(());
+            }
+        };
+
+        let tsig_error = dns::tsig_error(&response).unwrap_or_default();
+        if response.rcode() != 0 || tsig_error != 0 {
+            error!(
+                logger,
+                "Update zone {} on {} failed, rcode: {}, TSIG error: {}",
+                self.zone,
+                self.server,
+                dns::rcode_name(u16::from(response.rcode())),
+                dns::rcode_name(tsig_error)
+            );
+            return Err(());
+        }
+        // Spoofed responses are rejected
+        if let Some(ref key) = self.key {
+            if let Err(e) = key.verify(&response_data, &request, Self::now()) {
+                error!(
+                    logger,
+                    "Verify TSIG of response from {}({}) failed, error: {}", self.server, server, e
+                );
+                return Err(());
+            }
+        }
+
+        for domain in &self.domains {
+            let action_description: Vec<String> =
+                domain.select(recs).iter().map(|r| r.to_string()).collect();
+            info!(
+                logger,
+                "Update domain name {} to {} finished",
+                domain.name,
+                action_description.join(",")
+            );
+        }
+        Ok(0)
+    }
+
+    async fn plan_update(&self, options: SharedProgramOptions, recs: &[Record]) -> PlanResult {
+        let logger = self.logger();
+        let server = self.resolve_server().await?;
+
+        let mut ret = vec![];
+        for domain in &self.domains {
+            let new_records = resource_records(domain, recs, self.ttl);
+            let mut plan = DomainPlan::new("rfc2136", domain.name.clone());
+            let mut old_records = vec![];
+            for rtype in managed_types(domain) {
+                let mut request = dns::Message::query(
+                    daemon::random_u64() as u16,
+                    &domain.name,
+                    rtype,
+                    dns::CLASS_IN,
+                );
+                request.flags = 0;
+                let response = match request.encode() {
+                    Ok(data) => self.exchange(&options, server, &data).await,
+                    Err(e) => {
+                        error!(
+                            logger,
+                            "Encode DNS query of {} failed, error: {}", domain.name, e
+                        );
+                        return Err(());
+                    }
+                };
+                match response {
+                    Ok((response, _)) => old_records.extend(
+                        response
+                            .answers
+                            .into_iter()
+                            .filter(|r| r.rtype == rtype && r.rclass == dns::CLASS_IN),
+                    ),
+                    Err(e) => {
+                        error!(
+                            logger,
+                            "Query {} from {}({}) failed, error: {}",
+                            domain.name,
+                            self.server,
+                            server,
+                            e
+                        );
+                        return Err(());
+                    }
+                }
+            }
+
+            // records are compared by data, as TTL of records may be changed by others
+            let same = |a: &dns::ResourceRecord, b: &dns::ResourceRecord| {
+                a.rtype == b.rtype && a.data == b.data
+            };
+            for old_record in &old_records {
+                let action = if new_records.iter().any(|r| same(r, old_record)) {
+                    PlanAction::Keep
+                } else {
+                    PlanAction::Delete
+                };
+                match old_record.address() {
+                    Some(addr) => plan.add(action, type_name(old_record.rtype), addr),
+                    None => plan.add(
+                        action,
+                        type_name(old_record.rtype),
+                        hex::encode(&old_record.data),
+                    ),
+                }
+            }
+            for new_record in &new_records {
+                if !old_records.iter().any(|r| same(r, new_record)) {
+                    if let Some(addr) = new_record.address() {
+                        plan.add(PlanAction::Create, type_name(new_record.rtype), addr);
+                    }
+                }
+            }
+            ret.push(plan);
+        }
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use async_std::net::UdpSocket;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    fn create_key(secret: &str) -> dns::TsigKey {
+        dns::TsigKey::new("update-key", dns::TsigAlgorithm::HmacSha256, secret).unwrap()
+    }
+
+    fn create_driver(server: SocketAddr) -> Rfc2136 {
+        Rfc2136 {
+            server: server.to_string(),
+            zone: String::from("example.com"),
+            domains: vec![
+                "home.example.com".parse().unwrap(),
+                "vpn.example.com:A".parse().unwrap(),
+            ],
+            key: Some(create_key("ZGRucy1jbGktdGVzdC1rZXk=")),
+            ttl: 60,
+            tcp: false,
+            logger: Some(slog::Logger::root(slog::Discard, o!())),
+        }
+    }
+
+    /// Reply every request by raw response of `handler` on a local UDP port.
+    async fn mock_server<F>(handler: F) -> SocketAddr
+    where
+        F: Fn(dns::Message, &[u8]) -> Vec<u8> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let ret = socket.local_addr().unwrap();
+        async_std::task::spawn(async move {
+            let mut buffer = [0_u8; 4096];
+            while let Ok((size, from)) = socket.recv_from(&mut buffer).await {
+                let request = dns::Message::decode(&buffer[..size]).unwrap();
+                let response = handler(request, &buffer[..size]);
+                let _ = socket.send_to(&response, from).await;
+            }
+        });
+        ret
+    }
+
+    /// Response with only the question of request.
+    fn reply(request: &dns::Message) -> dns::Message {
+        let mut response = request.clone();
+        response.flags |= dns::FLAG_QR;
+        response.authorities.clear();
+        response.additionals.clear();
+        response
+    }
+
+    #[test]
+    fn test_update() {
+        async_std::task::block_on(async {
+            let options = test_util::default_options(Duration::from_secs(3));
+            let recs = vec![
+                Record::A(Ipv4Addr::new(192, 0, 2, 1)),
+                Record::Aaaa("2001:db8::1".parse().unwrap()),
+            ];
+
+            let (sender, requests) = std::sync::mpsc::channel();
+            let server = mock_server(move |request, data| {
+                let _ = sender.send(request.clone());
+                create_key("ZGRucy1jbGktdGVzdC1rZXk=")
+                    .sign_response(&reply(&request), data, Rfc2136::now())
+                    .unwrap()
+            })
+            .await;
+            let mut driver = create_driver(server);
+            assert_eq!(driver.update(options.clone(), &recs).await, Ok(0));
+
+            let request = requests.recv().unwrap();
+            assert_eq!(request.flags, dns::OPCODE_UPDATE);
+            assert_eq!(request.questions[0].name, "example.com");
+            assert_eq!(request.questions[0].qtype, dns::TYPE_SOA);
+            let updates: Vec<(&str, u16, u16, u32, &[u8])> = request
+                .authorities
+                .iter()
+                .map(|r| (r.name.as_str(), r.rtype, r.rclass, r.ttl, &r.data[..]))
+                .collect();
+            let aaaa = "2001:db8::1"
+                .parse::<std::net::Ipv6Addr>()
+                .unwrap()
+                .octets();
+            assert_eq!(
+                updates,
+                vec![
+                    ("home.example.com", dns::TYPE_A, dns::CLASS_ANY, 0, &[][..]),
+                    (
+                        "home.example.com",
+                        dns::TYPE_AAAA,
+                        dns::CLASS_ANY,
+                        0,
+                        &[][..]
+                    ),
+                    (
+                        "home.example.com",
+                        dns::TYPE_A,
+                        dns::CLASS_IN,
+                        60,
+                        &[192, 0, 2, 1][..]
+                    ),
+                    (
+                        "home.example.com",
+                        dns::TYPE_AAAA,
+                        dns::CLASS_IN,
+                        60,
+                        &aaaa[..]
+                    ),
+                    ("vpn.example.com", dns::TYPE_A, dns::CLASS_ANY, 0, &[][..]),
+                    (
+                        "vpn.example.com",
+                        dns::TYPE_A,
+                        dns::CLASS_IN,
+                        60,
+                        &[192, 0, 2, 1][..]
+                    ),
+                ]
+            );
+            assert_eq!(request.additionals.len(), 1);
+            assert_eq!(request.additionals[0].rtype, dns::TYPE_TSIG);
+            assert_eq!(request.additionals[0].name, "update-key");
+
+            // Rejected by BADSIG in TSIG record even if rcode is NOERROR
+            let server = mock_server(|request, _| {
+                let mut response = reply(&request);
+                let mut data =
+                    b"\x0bhmac-sha256\x00\x00\x00\x00\x00\x00\x00\x01\x2c\x00\x00".to_vec();
+                data.extend_from_slice(&response.id.to_be_bytes());
+                data.extend_from_slice(&[0, 16, 0, 0]);
+                response.additionals.push(dns::ResourceRecord {
+                    name: String::from("update-key"),
+                    rtype: dns::TYPE_TSIG,
+                    rclass: dns::CLASS_ANY,
+                    ttl: 0,
+                    data,
+                });
+                response.encode().unwrap()
+            })
+            .await;
+            let mut driver = create_driver(server);
+            assert_eq!(driver.update(options.clone(), &recs).await, Err(()));
+
+            // Rejected if REFUSED is tampered to NOERROR, not signed, or signed by another key
+            let server = mock_server(|request, data| {
+                let mut response = reply(&request);
+                response.flags |= 5;
+                let mut ret = create_key("ZGRucy1jbGktdGVzdC1rZXk=")
+                    .sign_response(&response, data, Rfc2136::now())
+                    .unwrap();
+                ret[3] &= 0xf0;
+                ret
+            })
+            .await;
+            let mut driver = create_driver(server);
+            assert_eq!(driver.update(options.clone(), &recs).await, Err(()));
+            let server = mock_server(|request, _| reply(&request).encode().unwrap()).await;
+            let mut driver = create_driver(server);
+            assert_eq!(driver.update(options.clone(), &recs).await, Err(()));
+            let server = mock_server(|request, data| {
+                create_key("c3Bvb2Zlcg==")
+                    .sign_response(&reply(&request), data, Rfc2136::now())
+                    .unwrap()
+            })
+            .await;
+            let mut driver = create_driver(server);
+            assert_eq!(driver.update(options.clone(), &recs).await, Err(()));
+
+            // Unsigned update is sent without TSIG record when no key is set
+            let (sender, requests) = std::sync::mpsc::channel();
+            let server = mock_server(move |request, _| {
+                let _ = sender.send(request.clone());
+                reply(&request).encode().unwrap()
+            })
+            .await;
+            let mut driver = create_driver(server);
+            driver.key = None;
+            assert_eq!(driver.update(options, &recs).await, Ok(0));
+            assert!(requests.recv().unwrap().additionals.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_plan() {
+        async_std::task::block_on(async {
+            let options = test_util::default_options(Duration::from_secs(3));
+            let server = mock_server(|request, _| {
+                let mut response = reply(&request);
+                if request.questions[0].qtype == dns::TYPE_A {
+                    for data in [[192, 0, 2, 1], [192, 0, 2, 2]] {
+                        response.answers.push(dns::ResourceRecord {
+                            name: request.questions[0].name.clone(),
+                            rtype: dns::TYPE_A,
+                            rclass: dns::CLASS_IN,
+                            ttl: 60,
+                            data: data.to_vec(),
+                        });
+                    }
+                }
+                response.encode().unwrap()
+            })
+            .await;
+            let driver = create_driver(server);
+            let recs = vec![
+                Record::A(Ipv4Addr::new(192, 0, 2, 1)),
+                Record::Aaaa("2001:db8::1".parse().unwrap()),
+            ];
+            let plans = driver.plan_update(options, &recs).await.unwrap();
+
+            let changes: Vec<(PlanAction, &str, &str)> = plans[0]
+                .changes
+                .iter()
+                .map(|c| (c.action, c.r#type.as_str(), c.content.as_str()))
+                .collect();
+            assert_eq!(
+                changes,
+                vec![
+                    (PlanAction::Keep, "A", "192.0.2.1"),
+                    (PlanAction::Delete, "A", "192.0.2.2"),
+                    (PlanAction::Create, "AAAA", "2001:db8::1"),
+                ]
+            );
+            assert_eq!(plans[1].domain, "vpn.example.com");
+            assert_eq!(plans[1].changes.len(), 2);
+        });
+    }
+}
@@ -0,0 +1,809 @@
+use futures::future::{self, BoxFuture, FutureExt};
+
+extern crate clap;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use regex::Regex;
+use reqwest::header::CONTENT_TYPE;
+
+use super::super::option;
+use super::sign;
+use super::{DomainPlan, Driver, DriverResult, ManagedDomain, PlanAction, PlanResult, Record};
+
+type SharedProgramOptions = super::SharedProgramOptions;
+type HttpMethod = super::HttpMethod;
+
+static ROUTE53_DEFAULT_ENDPOINT: &str = "https://route53.amazonaws.com";
+static ROUTE53_API_PATH: &str = "/2013-04-01";
+static ROUTE53_XMLNS: &str = "https://route53.amazonaws.com/doc/2013-04-01/";
+// Route 53 is a global service and requests are always signed for us-east-1
+static ROUTE53_REGION: &str = "us-east-1";
+static ROUTE53_SERVICE: &str = "route53";
+
+#[derive(Default)]
+pub struct Route53 {
+    endpoint: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: String,
+    hosted_zone_id: String,
+    zone: String,
+    /// Hosted zone id found by zone name, state keys are built from options only
+    resolved_zone_id: String,
+    domains: Vec<ManagedDomain>,
+    ttl: u32,
+    logger: Option<slog::Logger>,
+}
+
+/// Credentials and scope of AWS Signature Version 4.
+struct SignatureV4<'a> {
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    session_token: &'a str,
+    region: &'a str,
+    service: &'a str,
+}
+
+impl SignatureV4<'_> {
+    /// Headers to add to request, `query` must be canonical query string.
+    fn headers(
+        &self,
+        method: &str,
+        host: &str,
+        path: &str,
+        query: &str,
+        payload: &str,
+        amz_date: &str,
+    ) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("host", host.to_string()),
+            ("x-amz-date", amz_date.to_string()),
+        ];
+        if !self.session_token.is_empty() {
+            headers.push(("x-amz-security-token", self.session_token.to_string()));
+        }
+        let signed_headers: Vec<&str> = headers.iter().map(|(k, _)| *k).collect();
+        let signed_headers = signed_headers.join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            sign::uri_encode(path, false),
+            query,
+            headers
+                .iter()
+                .map(|(k, v)| format!("{}:{}\n", k, v.trim()))
+                .collect::<String>(),
+            signed_headers,
+            sign::sha256_hex(payload.as_bytes())
+        );
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sign::sha256_hex(canonical_request.as_bytes())
+        );
+
+        let mut key = format!("AWS4{}", self.secret_access_key).into_bytes();
+        for data in [date, self.region, self.service, "aws4_request"] {
+            key = sign::hmac_sha256(&key, data.as_bytes());
+        }
+        let signature = hex::encode(sign::hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        // host is set by HTTP client
+        headers.remove(0);
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        headers
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Route53RecordSet {
+    name: String,
+    r#type: String,
+    ttl: u32,
+    values: Vec<String>,
+}
+
+/// Text of all elements with this name.
+fn xml_elements(xml: &str, tag: &str) -> Vec<String> {
+    let pattern = format!(r"(?s)<{}(?:\s[^>]*)?>(.*?)</{}>", tag, tag);
+    match Regex::new(&pattern) {
+        Ok(re) => re
+            .captures_iter(xml)
+            .filter_map(|caps| caps.get(1))
+            .map(|m| m.as_str().trim().to_string())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    xml_elements(xml, tag).into_iter().next()
+}
+
+fn xml_escape(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Route 53 always returns names with the trailing dot.
+fn absolute_name(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+/// Route 53 escapes characters like `*` as `\052` in names.
+fn unescape_name(name: &str) -> String {
+    let mut ret = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(pos) = rest.find('\\') {
+        ret.push_str(&rest[..pos]);
+        match rest
+            .get(pos + 1..pos + 4)
+            .and_then(|v| u8::from_str_radix(v, 8).ok())
+        {
+            Some(c) => {
+                ret.push(c as char);
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                ret.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Id of the public hosted zone, private ones with the same name are skipped.
+fn parse_hosted_zone_id(xml: &str, zone: &str) -> Option<String> {
+    let zone = absolute_name(zone);
+    xml_elements(xml, "HostedZone")
+        .into_iter()
+        .find_map(|hosted_zone| {
+            let name = unescape_name(&xml_text(&hosted_zone, "Name")?);
+            if !name.eq_ignore_ascii_case(&zone)
+                || xml_text(&hosted_zone, "PrivateZone").as_deref() == Some("true")
+            {
+                return None;
+            }
+            xml_text(&hosted_zone, "Id").map(|id| id.trim_start_matches("/hostedzone/").to_string())
+        })
+}
+
+/// Record sets of A and AAAA, alias record sets are ignored.
+fn parse_record_sets(xml: &str, name: &str) -> Vec<Route53RecordSet> {
+    let name = absolute_name(name);
+    xml_elements(xml, "ResourceRecordSet")
+        .into_iter()
+        .filter_map(|record_set| {
+            let ret = Route53RecordSet {
+                name: unescape_name(&xml_text(&record_set, "Name")?),
+                r#type: xml_text(&record_set, "Type")?,
+                ttl: xml_text(&record_set, "TTL")?.parse().ok()?,
+                values: xml_elements(&record_set, "Value"),
+            };
+            if ret.name.eq_ignore_ascii_case(&name) && (ret.r#type == "A" || ret.r#type == "AAAA") {
+                Some(ret)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// UPSERT record sets of managed types which are changed, and DELETE record sets of managed types
+/// which have no address now.
+fn build_changes(
+    domain: &ManagedDomain,
+    recs: &[Record],
+    ttl: u32,
+    old_record_sets: &[Route53RecordSet],
+) -> Vec<(&'static str, Route53RecordSet)> {
+    let mut ret = vec![];
+    for r#type in ["A", "AAAA"] {
+        if !domain.manages(r#type) {
+            continue;
+        }
+
+        let mut values: Vec<String> = recs
+            .iter()
+            .filter_map(|r| match r {
+                Record::A(v) if r#type == "A" => Some(v.to_string()),
+                Record::Aaaa(v) if r#type == "AAAA" => Some(v.to_string()),
+                _ => None,
+            })
+            .collect();
+        values.sort();
+        let old_record_set = old_record_sets.iter().find(|r| r.r#type == r#type);
+        if values.is_empty() {
+            if let Some(old_record_set) = old_record_set {
+                ret.push(("DELETE", old_record_set.clone()));
+            }
+            continue;
+        }
+
+        let record_set = Route53RecordSet {
+            name: absolute_name(&domain.name),
+            r#type: r#type.to_string(),
+            ttl,
+            values,
+        };
+        let unchanged = old_record_set.is_some_and(|old| {
+            let mut old_values = old.values.clone();
+            old_values.sort();
+            old.ttl == ttl && old_values == record_set.values
+        });
+        if !unchanged {
+            ret.push(("UPSERT", record_set));
+        }
+    }
+    ret
+}
+
+fn change_batch_xml(changes: &[(&'static str, Route53RecordSet)]) -> String {
+    let mut ret = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><ChangeResourceRecordSetsRequest xmlns="{}"><ChangeBatch><Comment>Updated by {}</Comment><Changes>"#,
+        ROUTE53_XMLNS,
+        crate_name!()
+    );
+    for (action, record_set) in changes {
+        ret.push_str(&format!(
+            "<Change><Action>{}</Action><ResourceRecordSet><Name>{}</Name><Type>{}</Type><TTL>{}</TTL><ResourceRecords>",
+            action,
+            xml_escape(&record_set.name),
+            record_set.r#type,
+            record_set.ttl
+        ));
+        for value in &record_set.values {
+            ret.push_str(&format!(
+                "<ResourceRecord><Value>{}</Value></ResourceRecord>",
+                xml_escape(value)
+            ));
+        }
+        ret.push_str("</ResourceRecords></ResourceRecordSet></Change>");
+    }
+    ret.push_str("</Changes></ChangeBatch></ChangeResourceRecordSetsRequest>");
+    ret
+}
+
+impl Driver for Route53 {
+    fn initialize(&mut self, app: Command) -> Command {
+        app.arg(
+            Arg::new("r53-access-key-id")
+                .long("r53-access-key-id")
+                .value_name("ACCESS KEY ID")
+                .help("Set access key id of AWS Route 53 API, AWS_ACCESS_KEY_ID is used if it's not set"),
+        ).arg(
+            Arg::new("r53-secret-access-key")
+                .long("r53-secret-access-key")
+                .value_name("SECRET ACCESS KEY")
+                .help("Set secret access key of AWS Route 53 API, AWS_SECRET_ACCESS_KEY is used if it's not set"),
+        ).arg(
+            Arg::new("r53-session-token")
+                .long("r53-session-token")
+                .value_name("SESSION TOKEN")
+                .help("Set session token of temporary credentials, AWS_SESSION_TOKEN is used if it's not set"),
+        ).arg(
+            Arg::new("r53-hosted-zone-id")
+                .long("r53-hosted-zone-id")
+                .value_name("HOSTED ZONE ID")
+                .help("Set hosted zone id of AWS Route 53, --r53-hosted-zone-id or --r53-zone must be set when using Route 53"),
+        ).arg(
+            Arg::new("r53-zone")
+                .long("r53-zone")
+                .value_name("ZONE")
+                .help("Set zone name to find hosted zone id of AWS Route 53, private hosted zones are ignored, --r53-hosted-zone-id or --r53-zone must be set when using Route 53"),
+        ).arg(
+            Arg::new("r53-domain")
+                .long("r53-domain")
+                .value_name("DOMAIN")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Add domain to update using AWS Route 53 API, DOMAIN:A or DOMAIN:AAAA to only update records of these types"),
+        ).arg(
+            Arg::new("r53-ttl")
+                .long("r53-ttl")
+                .value_name("SECONDS")
+                .default_value("300")
+                .help("Set TTL of records updated by AWS Route 53 API"),
+        ).arg(
+            Arg::new("r53-endpoint")
+                .long("r53-endpoint")
+                .value_name("URL")
+                .help("Set endpoint of AWS Route 53 API, https://route53.amazonaws.com is used if it's not set"),
+        )
+    }
+
+    fn parse_options(&mut self, matches: &ArgMatches, options: &mut SharedProgramOptions) {
+        let credential = |name: &str, env_name: &str| {
+            let ret = option::unwraper_option_or(matches, name, String::default());
+            if ret.is_empty() {
+                std::env::var(env_name).unwrap_or_default()
+            } else {
+                ret
+            }
+        };
+        self.access_key_id = credential("r53-access-key-id", "AWS_ACCESS_KEY_ID");
+        self.secret_access_key = credential("r53-secret-access-key", "AWS_SECRET_ACCESS_KEY");
+        self.session_token = credential("r53-session-token", "AWS_SESSION_TOKEN");
+        self.hosted_zone_id =
+            option::unwraper_option_or(matches, "r53-hosted-zone-id", String::default());
+        self.zone = option::unwraper_option_or(matches, "r53-zone", String::default());
+        self.ttl = option::unwraper_from_str_or(matches, "r53-ttl", 300);
+        self.endpoint = option::unwraper_option_or(
+            matches,
+            "r53-endpoint",
+            String::from(ROUTE53_DEFAULT_ENDPOINT),
+        );
+
+        if !self.access_key_id.is_empty()
+            && !self.secret_access_key.is_empty()
+            && (!self.hosted_zone_id.is_empty() || !self.zone.is_empty())
+        {
+            self.logger = Some(options.create_logger("Route53"));
+
+            self.domains.extend(option::unwraper_multiple_values(
+                matches,
+                "r53-domain",
+                self.logger.as_ref().unwrap(),
+                "domain",
+            ));
+        }
+    }
+
+    fn state_keys(&self) -> Vec<String> {
+        if self.logger.is_none() {
+            return vec![];
+        }
+
+        let zone = if self.hosted_zone_id.is_empty() {
+            &self.zone
+        } else {
+            &self.hosted_zone_id
+        };
+        self.domains
+            .iter()
+            .map(|domain| format!("route53:{}:{}", zone, domain))
+            .collect()
+    }
+
+    fn run<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, DriverResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() || self.domains.is_empty() {
+            return future::ready(Ok(0)).boxed();
+        }
+
+        self.update(options.clone(), recs).boxed()
+    }
+
+    fn plan<'a, 'b, 'c>(
+        &'a mut self,
+        options: &SharedProgramOptions,
+        recs: &'c [Record],
+    ) -> BoxFuture<'b, PlanResult>
+    where
+        'a: 'b,
+        'c: 'a,
+    {
+        if self.logger.is_none() || self.domains.is_empty() {
+            return future::ready(Ok(vec![])).boxed();
+        }
+
+        self.plan_update(options.clone(), recs).boxed()
+    }
+}
+
+impl Route53 {
+    fn logger(&self) -> &slog::Logger {
+        self.logger.as_ref().unwrap()
+    }
+
+    /// Send signed request and return the response body, or the error message.
+    async fn request(
+        &self,
+        options: &SharedProgramOptions,
+        method: HttpMethod,
+        path: &str,
+        query: &[(&str, &str)],
+        payload: String,
+    ) -> Result<String, String> {
+        let query = sign::canonical_query(query);
+        let mut url = format!("{}{}", self.endpoint.trim_end_matches('/'), path);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, query);
+        }
+        let parsed_url = reqwest::Url::parse(&url).map_err(|e| e.to_string())?;
+        let host = match (parsed_url.host_str(), parsed_url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            _ => return Err(format!("no host in {}", url)),
+        };
+
+        let signature = SignatureV4 {
+            access_key_id: &self.access_key_id,
+            secret_access_key: &self.secret_access_key,
+            session_token: &self.session_token,
+            region: ROUTE53_REGION,
+            service: ROUTE53_SERVICE,
+        };
+        let amz_date = sign::iso8601_basic(&time::OffsetDateTime::now_utc());
+        let method_name = if method == HttpMethod::Post {
+            "POST"
+        } else {
+            "GET"
+        };
+
+        let mut cli = options.http(method, &url);
+        for (name, value) in
+            signature.headers(method_name, &host, path, &query, &payload, &amz_date)
+        {
+            cli = cli.header(name, value);
+        }
+        if !payload.is_empty() {
+            cli = cli.header(CONTENT_TYPE, "text/xml").body(payload);
+        }
+
+        let rsp = cli.send().await.map_err(|e| e.to_string())?;
+        let status = rsp.status();
+        let rsp_text = rsp.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!(
+                "{} {}: {}",
+                status,
+                xml_text(&rsp_text, "Code").unwrap_or_default(),
+                xml_text(&rsp_text, "Message").unwrap_or(rsp_text)
+            ));
+        }
+        Ok(rsp_text)
+    }
+
+    async fn get_hosted_zone_id(&mut self, options: &SharedProgramOptions) -> Result<String, ()> {
+        if !self.hosted_zone_id.is_empty() {
+            return Ok(self.hosted_zone_id.clone());
+        }
+        if !self.resolved_zone_id.is_empty() {
+            return Ok(self.resolved_zone_id.clone());
+        }
+
+        // Hosted zones are sorted by name, so all zones with this name are in the first page
+        let path = format!("{}/hostedzonesbyname", ROUTE53_API_PATH);
+        let query = [("dnsname", self.zone.as_str()), ("maxitems", "100")];
+        let rsp = match self
+            .request(options, HttpMethod::Get, &path, &query, String::default())
+            .await
+        {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    self.logger(),
+                    "List hosted zones by name {} failed, error: {}", self.zone, e
+                );
+                return Err(());
+            }
+        };
+
+        match parse_hosted_zone_id(&rsp, &self.zone) {
+            Some(id) => {
+                debug!(self.logger(), "Find hosted zone id {} of {}", id, self.zone);
+                self.resolved_zone_id = id.clone();
+                Ok(id)
+            }
+            None => {
+                error!(self.logger(), "Hosted zone {} not found", self.zone);
+                Err(())
+            }
+        }
+    }
+
+    async fn get_record_sets(
+        &self,
+        options: &SharedProgramOptions,
+        hosted_zone_id: &str,
+        domain: &ManagedDomain,
+    ) -> Result<Vec<Route53RecordSet>, ()> {
+        let path = format!("{}/hostedzone/{}/rrset", ROUTE53_API_PATH, hosted_zone_id);
+        let name = absolute_name(&domain.name);
+        let query = [("name", name.as_str()), ("maxitems", "100")];
+        match self
+            .request(options, HttpMethod::Get, &path, &query, String::default())
+            .await
+        {
+            Ok(rsp) => Ok(parse_record_sets(&rsp, &domain.name)
+                .into_iter()
+                .filter(|r| domain.manages(&r.r#type))
+                .collect()),
+            Err(e) => {
+                error!(
+                    self.logger(),
+                    "List record sets of {} failed, error: {}", domain.name, e
+                );
+                Err(())
+            }
+        }
+    }
+
+    async fn plan_update(&mut self, options: SharedProgramOptions, recs: &[Record]) -> PlanResult {
+        let hosted_zone_id = self.get_hosted_zone_id(&options).await?;
+
+        let mut ret = vec![];
+        for domain in &self.domains {
+            let old_record_sets = self
+                .get_record_sets(&options, &hosted_zone_id, domain)
+                .await?;
+            let new_values: Vec<(&str, String)> = domain
+                .select(recs)
+                .iter()
+                .filter_map(|r| match r {
+                    Record::A(v) => Some(("A", v.to_string())),
+                    Record::Aaaa(v) => Some(("AAAA", v.to_string())),
+                    _ => None,
+                })
+                .collect();
+
+            let mut plan = DomainPlan::new("route53", domain.name.clone());
+            for old_record_set in &old_record_sets {
+                for value in &old_record_set.values {
+                    let action = if new_values
+                        .iter()
+                        .any(|(t, v)| *t == old_record_set.r#type && v == value)
+                    {
+                        PlanAction::Keep
+                    } else {
+                        PlanAction::Delete
+                    };
+                    plan.add(action, &old_record_set.r#type, value);
+                }
+            }
+            for (r#type, value) in &new_values {
+                if !old_record_sets
+                    .iter()
+                    .any(|r| r.r#type == *r#type && r.values.contains(value))
+                {
+                    plan.add(PlanAction::Create, r#type, value);
+                }
+            }
+            ret.push(plan);
+        }
+
+        Ok(ret)
+    }
+
+    async fn update(&mut self, options: SharedProgramOptions, recs: &[Record]) -> DriverResult {
+        let hosted_zone_id = self.get_hosted_zone_id(&options).await?;
+        let logger = self.logger();
+
+        let mut changes = vec![];
+        for domain in &self.domains {
+            let old_record_sets = self
+                .get_record_sets(&options, &hosted_zone_id, domain)
+                .await?;
+            if !old_record_sets.is_empty() {
+                debug!(logger, "Old records:");
+                for log_item in &old_record_sets {
+                    debug!(logger, "     -- {:?}", log_item);
+                }
+            }
+            changes.extend(build_changes(domain, recs, self.ttl, &old_record_sets));
+        }
+
+        if changes.is_empty() {
+            info!(logger, "Records not changed, skip updating");
+            return Ok(0);
+        }
+        debug!(logger, "Pending to change:");
+        for log_item in &changes {
+            debug!(logger, "     -- {:?}", log_item);
+        }
+
+        let path = format!("{}/hostedzone/{}/rrset/", ROUTE53_API_PATH, hosted_zone_id);
+        match self
+            .request(
+                &options,
+                HttpMethod::Post,
+                &path,
+                &[],
+                change_batch_xml(&changes),
+            )
+            .await
+        {
+            Ok(rsp) => {
+                debug!(
+                    logger,
+                    "Change {} is {}",
+                    xml_text(&rsp, "Id").unwrap_or_default(),
+                    xml_text(&rsp, "Status").unwrap_or_default()
+                );
+            }
+            Err(e) => {
+                error!(
+                    logger,
+                    "Change record sets of hosted zone {} failed, error: {}", hosted_zone_id, e
+                );
+                return Err(());
+            }
+        }
+
+        for domain in &self.domains {
+            let action_description: Vec<String> =
+                domain.select(recs).iter().map(|r| r.to_string()).collect();
+            info!(
+                logger,
+                "Update domain name {} to {} finished",
+                domain.name,
+                action_description.join(",")
+            );
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_signature_v4() {
+        // get-vanilla of AWS Signature Version 4 test suite
+        let signature = SignatureV4 {
+            access_key_id: "AKIDEXAMPLE",
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            session_token: "",
+            region: "us-east-1",
+            service: "service",
+        };
+        let headers = signature.headers(
+            "GET",
+            "example.amazonaws.com",
+            "/",
+            "",
+            "",
+            "20150830T123600Z",
+        );
+        assert_eq!(
+            headers,
+            vec![
+                ("x-amz-date", String::from("20150830T123600Z")),
+                ("authorization", String::from("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_changes() {
+        let recs = vec![
+            Record::A(Ipv4Addr::new(192, 0, 2, 2)),
+            Record::A(Ipv4Addr::new(192, 0, 2, 1)),
+        ];
+        let old_record_sets = vec![
+            Route53RecordSet {
+                name: String::from("home.example.com."),
+                r#type: String::from("A"),
+                ttl: 300,
+                values: vec![String::from("192.0.2.1"), String::from("192.0.2.2")],
+            },
+            Route53RecordSet {
+                name: String::from("home.example.com."),
+                r#type: String::from("AAAA"),
+                ttl: 300,
+                values: vec![String::from("2001:db8::1")],
+            },
+        ];
+
+        let domain: ManagedDomain = "home.example.com".parse().unwrap();
+        let changes = build_changes(&domain, &recs, 300, &old_record_sets);
+        assert_eq!(changes, vec![("DELETE", old_record_sets[1].clone())]);
+
+        let domain: ManagedDomain = "home.example.com:A".parse().unwrap();
+        let changes = build_changes(&domain, &recs, 60, &old_record_sets);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].0, "UPSERT");
+        assert_eq!(changes[0].1.ttl, 60);
+        assert_eq!(changes[0].1.values, vec!["192.0.2.1", "192.0.2.2"]);
+    }
+
+    #[test]
+    fn test_update() {
+        const HOSTED_ZONES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListHostedZonesByNameResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+<HostedZones><HostedZone><Id>/hostedzone/Z0000000001</Id><Name>example.com.</Name>
+<CallerReference>vpc</CallerReference><Config><PrivateZone>true</PrivateZone></Config>
+<ResourceRecordSetCount>2</ResourceRecordSetCount></HostedZone>
+<HostedZone><Id>/hostedzone/Z0123456789</Id><Name>example.com.</Name>
+<CallerReference>ddns</CallerReference><Config><PrivateZone>false</PrivateZone></Config>
+<ResourceRecordSetCount>4</ResourceRecordSetCount></HostedZone></HostedZones>
+<DNSName>example.com</DNSName><IsTruncated>false</IsTruncated><MaxItems>100</MaxItems></ListHostedZonesByNameResponse>"#;
+        const RECORD_SETS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><ResourceRecordSets>
+<ResourceRecordSet><Name>home.example.com.</Name><Type>A</Type><TTL>300</TTL>
+<ResourceRecords><ResourceRecord><Value>192.0.2.9</Value></ResourceRecord></ResourceRecords></ResourceRecordSet>
+<ResourceRecordSet><Name>home.example.com.</Name><Type>TXT</Type><TTL>300</TTL>
+<ResourceRecords><ResourceRecord><Value>"v=spf1 -all"</Value></ResourceRecord></ResourceRecords></ResourceRecordSet>
+<ResourceRecordSet><Name>www.example.com.</Name><Type>A</Type><TTL>300</TTL>
+<ResourceRecords><ResourceRecord><Value>192.0.2.10</Value></ResourceRecord></ResourceRecords></ResourceRecordSet>
+</ResourceRecordSets><IsTruncated>false</IsTruncated><MaxItems>100</MaxItems></ListResourceRecordSetsResponse>"#;
+        const CHANGE_INFO: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ChangeResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+<ChangeInfo><Id>/change/C0123</Id><Status>PENDING</Status><SubmittedAt>2024-01-01T00:00:00Z</SubmittedAt></ChangeInfo>
+</ChangeResourceRecordSetsResponse>"#;
+        const ERROR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ErrorResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><Error><Type>Sender</Type>
+<Code>Throttling</Code><Message>Rate exceeded</Message></Error><RequestId>R1</RequestId></ErrorResponse>"#;
+
+        async_std::task::block_on(async {
+            let (address, server) = test_util::http_stand_in(vec![
+                (200, String::from(HOSTED_ZONES)),
+                (200, String::from(RECORD_SETS)),
+                (200, String::from(CHANGE_INFO)),
+                (400, String::from(ERROR)),
+            ])
+            .await;
+
+            let options = test_util::default_options(Duration::from_secs(3));
+            let mut driver = Route53 {
+                endpoint: format!("http://{}", address),
+                access_key_id: String::from("AKIDEXAMPLE"),
+                secret_access_key: String::from("SECRET"),
+                session_token: String::from("TOKEN"),
+                hosted_zone_id: String::default(),
+                zone: String::from("example.com"),
+                resolved_zone_id: String::default(),
+                domains: vec!["home.example.com".parse().unwrap()],
+                ttl: 300,
+                logger: Some(slog::Logger::root(slog::Discard, o!())),
+            };
+            let recs = vec![Record::A(Ipv4Addr::new(192, 0, 2, 1))];
+            let state_keys = vec![String::from("route53:example.com:home.example.com")];
+            assert_eq!(driver.state_keys(), state_keys);
+            assert_eq!(driver.update(options.clone(), &recs).await, Ok(0));
+            // Private hosted zone is skipped, and found id doesn't change state keys
+            assert_eq!(driver.resolved_zone_id, "Z0123456789");
+            assert_eq!(driver.state_keys(), state_keys);
+            // Hosted zone id is cached, and error of XML response fails the update
+            assert_eq!(driver.update(options, &recs).await, Err(()));
+
+            let requests = server.await;
+            assert_eq!(requests.len(), 4);
+            assert!(requests[0].starts_with(
+                "GET /2013-04-01/hostedzonesbyname?dnsname=example.com&maxitems=100 "
+            ));
+            assert!(requests[1].starts_with(
+                "GET /2013-04-01/hostedzone/Z0123456789/rrset?maxitems=100&name=home.example.com. "
+            ));
+            assert!(requests[2].starts_with("POST /2013-04-01/hostedzone/Z0123456789/rrset/ "));
+            assert!(requests[3].starts_with(
+                "GET /2013-04-01/hostedzone/Z0123456789/rrset?maxitems=100&name=home.example.com. "
+            ));
+            for request in &requests {
+                let request = request.to_ascii_lowercase();
+                assert!(request.contains("authorization: aws4-hmac-sha256 credential=akidexample/"));
+                assert!(request.contains("signedheaders=host;x-amz-date;x-amz-security-token"));
+                assert!(request.contains("x-amz-security-token: token"));
+            }
+            assert!(requests[2].contains(
+                "<Change><Action>UPSERT</Action><ResourceRecordSet><Name>home.example.com.</Name><Type>A</Type><TTL>300</TTL><ResourceRecords><ResourceRecord><Value>192.0.2.1</Value></ResourceRecord></ResourceRecords></ResourceRecordSet></Change></Changes>"
+            ));
+        });
+    }
+}
@@ -0,0 +1,96 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+pub fn hmac_sha1(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length
+    let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Percent encode all characters except unreserved characters of RFC 3986, which is required by
+/// signatures of cloud APIs.
+pub fn uri_encode(input: &str, encode_slash: bool) -> String {
+    let mut ret = String::with_capacity(input.len());
+    for c in input.bytes() {
+        match c {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                ret.push(c as char)
+            }
+            b'/' if !encode_slash => ret.push('/'),
+            _ => ret.push_str(&format!("%{:02X}", c)),
+        }
+    }
+    ret
+}
+
+/// Encoded query string sorted by name.
+pub fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut query: Vec<(String, String)> = query
+        .iter()
+        .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+        .collect();
+    query.sort();
+    query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// Format time as 20150830T123600Z.
+pub fn iso8601_basic(time: &time::OffsetDateTime) -> String {
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+/// Format time as 2015-08-30T12:36:00Z.
+pub fn iso8601(time: &time::OffsetDateTime) -> String {
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("a-b_c.d~e", true), "a-b_c.d~e");
+        assert_eq!(uri_encode("/a b/*", false), "/a%20b/%2A");
+        assert_eq!(uri_encode("/a b/*", true), "%2Fa%20b%2F%2A");
+        assert_eq!(
+            canonical_query(&[("name", "home.example.com."), ("maxitems", "1")]),
+            "maxitems=1&name=home.example.com."
+        );
+        assert_eq!(canonical_query(&[("A-B", "1"), ("A", "2")]), "A=2&A-B=1");
+        let time = time::OffsetDateTime::from_unix_timestamp(1440938160).unwrap();
+        assert_eq!(iso8601_basic(&time), "20150830T123600Z");
+        assert_eq!(iso8601(&time), "2015-08-30T12:36:00Z");
+    }
+}
@@ -31,14 +31,28 @@ extern crate reqwest;
 
 // use tokio::prelude::*;
 
+use futures::future::{self, FutureExt};
+
+mod daemon;
 mod detector;
+mod dns;
 mod driver;
 mod option;
+mod profile;
+mod state;
+#[cfg(test)]
+mod test_util;
 
 fn register_detectors() -> Vec<Box<dyn detector::Detector>> {
     vec![
         Box::<detector::SetIpDetector>::default(),
         Box::<detector::GetIpByUrlDetector>::default(),
+        Box::<detector::InterfaceDetector>::default(),
+        Box::<detector::StunDetector>::default(),
+        Box::<detector::DnsQueryDetector>::default(),
+        Box::<detector::UpnpDetector>::default(),
+        Box::<detector::CommandDetector>::default(),
+        Box::<detector::FileDetector>::default(),
     ]
 }
 
@@ -46,51 +60,109 @@ fn register_drivers() -> Vec<Box<dyn driver::Driver>> {
     vec![
         Box::<driver::Cloudflare>::default(),
         Box::<driver::Dnspod>::default(),
+        Box::<driver::Rfc2136>::default(),
+        Box::<driver::Route53>::default(),
+        Box::<driver::Aliyun>::default(),
+        Box::<driver::Dyndns2>::default(),
     ]
 }
 
-async fn real_main() -> i32 {
-    let mut detectors = register_detectors();
-    let mut drivers = register_drivers();
+fn create_app() -> clap::Command {
     let mut app = option::app();
 
     // register for command options
-    for ref mut detector in &mut detectors {
+    for ref mut detector in &mut register_detectors() {
         app = detector.as_mut().initialize(app);
+        app = detector::AddressFilter::initialize(app, detector.name());
     }
+    app = detector::AddressFilter::initialize(app, profile::GLOBAL_FILTER_PREFIX);
+    app = detector::Ipv6Composer::initialize(app);
 
-    for ref mut driver in &mut drivers {
+    for ref mut driver in &mut register_drivers() {
         app = driver.initialize(app);
     }
 
-    let (matches, mut options) = option::parse_options(app);
+    app
+}
 
-    // parse command options
-    for ref mut detector in &mut detectors {
-        detector.as_mut().parse_options(&matches, &mut options);
+fn print_plans(profiles: &[profile::Profile]) {
+    let dry_run_profiles: Vec<&profile::Profile> =
+        profiles.iter().filter(|p| p.options().dry_run).collect();
+    if dry_run_profiles.is_empty() {
+        return;
     }
 
-    for ref mut driver in &mut drivers {
-        driver.parse_options(&matches, &mut options);
+    let plans: Vec<&driver::DomainPlan> = dry_run_profiles.iter().flat_map(|p| p.plans()).collect();
+    if dry_run_profiles[0].options().dry_run_format == "json" {
+        match serde_json::to_string_pretty(&plans) {
+            Ok(v) => println!("{}", v),
+            Err(e) => eprintln!("Serialize plan failed, error: {}", e),
+        }
+        return;
     }
 
-    // System::new(crate_name!()).block_on(async move {
-    let mut records: Vec<detector::Record> = vec![];
-    for ref mut detector in &mut detectors {
-        if let Ok(res) = detector.as_mut().run(&mut options).await {
-            records.extend(res.iter().cloned());
+    for plan in plans {
+        if profiles.len() > 1 {
+            println!("[{}] {} {}", plan.profile, plan.driver, plan.domain);
+        } else {
+            println!("{} {}", plan.driver, plan.domain);
+        }
+        if plan.changes.is_empty() {
+            println!("    (no record)");
+        }
+        for change in &plan.changes {
+            println!(
+                "    {:<8}{:<8}{}",
+                change.action.to_string(),
+                change.r#type,
+                change.content
+            );
         }
     }
+}
 
-    records.dedup();
-    let mut exit_code: i32 = 0;
-    for ref mut driver in &mut drivers {
-        if (driver.run(&options, &records).await).is_err() {
-            exit_code = 1;
+async fn real_main() -> i32 {
+    let mut profiles: Vec<profile::Profile> = option::parse_options(create_app())
+        .into_iter()
+        .map(|(name, matches, options)| {
+            profile::Profile::new(
+                name,
+                register_detectors(),
+                register_drivers(),
+                &matches,
+                options,
+            )
+        })
+        .collect();
+
+    let shutdown: profile::ShutdownSignal = {
+        let options = profiles[0].options().clone();
+        daemon::shutdown_signal(options).boxed().shared()
+    };
+
+    let results = future::join_all(profiles.iter_mut().map(|p| p.run(shutdown.clone()))).await;
+
+    print_plans(&profiles);
+
+    let failed_count = results.iter().filter(|success| !**success).count();
+    if profiles.len() > 1 {
+        for (profile, success) in profiles.iter().zip(results.iter()) {
+            let logger = profile.options().create_logger("Profile");
+            if *success {
+                info!(logger, "Profile {} finished", profile.name());
+            } else {
+                error!(logger, "Profile {} failed", profile.name());
+            }
         }
     }
 
-    exit_code
+    if failed_count == 0 {
+        0
+    } else if failed_count == profiles.len() {
+        1
+    } else {
+        2
+    }
 }
 
 fn main() {
@@ -0,0 +1,316 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+
+use serde_json::Value;
+
+pub type ConfigEntry = (String, Vec<Value>);
+pub type ProfileEntries = (String, Vec<ConfigEntry>);
+
+/// Load a TOML or YAML configuration file.
+/// The format is chosen by file extension, and TOML is used when the extension is unknown.
+pub fn load_file<P>(path: P) -> Result<Value, String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Read config file {} failed, error: {}", path.display(), e))?;
+
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    let value = match extension.as_str() {
+        "yaml" | "yml" => serde_yaml::from_str::<Value>(&content).map_err(|e| e.to_string()),
+        _ => toml::from_str::<Value>(&content).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("Parse config file {} failed, error: {}", path.display(), e))?;
+
+    match value {
+        Value::Object(_) => Ok(value),
+        Value::Null => Ok(Value::Object(serde_json::Map::new())),
+        _ => Err(format!(
+            "Config file {} must contain a table of options",
+            path.display()
+        )),
+    }
+}
+
+/// Flatten configure values into (long option name, values) pairs.
+/// Nested tables are joined by `-`, so `[cf] token = "..."` is the same as `cf-token = "..."`.
+pub fn flatten(value: &Value, prefix: &str, output: &mut Vec<ConfigEntry>) {
+    match value {
+        Value::Object(table) => {
+            for (key, child) in table {
+                let key = key.replace('_', "-");
+                let name = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}-{}", prefix, key)
+                };
+                flatten(child, &name, output);
+            }
+        }
+        Value::Array(values) => output.push((prefix.to_string(), values.clone())),
+        Value::Null => {}
+        _ => output.push((prefix.to_string(), vec![value.clone()])),
+    }
+}
+
+/// Split configure into global entries and entries of every `[profile.<NAME>]` table.
+pub fn split_profiles(config: &Value) -> Result<(Vec<ConfigEntry>, Vec<ProfileEntries>), String> {
+    let mut global = serde_json::Map::new();
+    let mut profiles = vec![];
+    if let Value::Object(table) = config {
+        for (key, value) in table {
+            if key != "profile" {
+                global.insert(key.clone(), value.clone());
+                continue;
+            }
+
+            match value {
+                Value::Object(profile_tables) => {
+                    for (name, profile_value) in profile_tables {
+                        if !profile_value.is_object() {
+                            return Err(format!(
+                                "profile '{}' in config file must be a table",
+                                name
+                            ));
+                        }
+                        let mut entries = vec![];
+                        flatten(profile_value, "", &mut entries);
+                        profiles.push((name.clone(), entries));
+                    }
+                }
+                _ => {
+                    return Err(String::from(
+                        "'profile' in config file must be a table of profiles",
+                    ))
+                }
+            }
+        }
+    }
+
+    let mut global_entries = vec![];
+    flatten(&Value::Object(global), "", &mut global_entries);
+    Ok((global_entries, profiles))
+}
+
+/// Only keep profiles selected by `--profile`, all names must be found in configure file.
+pub fn select_profiles(
+    profiles: &mut Vec<ProfileEntries>,
+    selected: &[String],
+) -> Result<(), String> {
+    if selected.is_empty() {
+        return Ok(());
+    }
+    if profiles.is_empty() {
+        return Err(format!(
+            "profile '{}' is selected but no [profile.<NAME>] is set in config file",
+            selected.join("', '")
+        ));
+    }
+    if let Some(name) = selected
+        .iter()
+        .find(|s| !profiles.iter().any(|(n, _)| n == *s))
+    {
+        return Err(format!("profile '{}' not found in config file", name));
+    }
+
+    profiles.retain(|(n, _)| selected.contains(n));
+    Ok(())
+}
+
+/// Entries of profile override global entries with the same option name.
+pub fn merge_entries(global: &[ConfigEntry], profile: &[ConfigEntry]) -> Vec<ConfigEntry> {
+    let mut ret: Vec<ConfigEntry> = global
+        .iter()
+        .filter(|(name, _)| !profile.iter().any(|(n, _)| n == name))
+        .cloned()
+        .collect();
+    ret.extend(profile.iter().cloned());
+    ret
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(v) => Some(v.clone()),
+        Value::Number(v) => Some(v.to_string()),
+        Value::Bool(v) => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+/// Convert configure values into command line arguments.
+/// Options which are already set by command line are skipped, so command line always overrides the file.
+pub fn build_arguments(
+    app: &Command,
+    cli_matches: &ArgMatches,
+    entries: &[ConfigEntry],
+) -> Result<Vec<OsString>, clap::Error> {
+    let mut ret = vec![];
+    for (name, values) in entries {
+        let arg = match app
+            .get_arguments()
+            .find(|a| a.get_long() == Some(name.as_str()) && a.get_id() != "config")
+        {
+            Some(a) => a,
+            None => {
+                return Err(app.clone().error(
+                    ErrorKind::UnknownArgument,
+                    format!("unknown key '{}' in config file", name),
+                ))
+            }
+        };
+
+        if cli_matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+
+        if let ArgAction::SetTrue = arg.get_action() {
+            match values.as_slice() {
+                [Value::Bool(true)] => ret.push(OsString::from(format!("--{}", name))),
+                [Value::Bool(false)] => {}
+                _ => {
+                    return Err(app.clone().error(
+                        ErrorKind::InvalidValue,
+                        format!("key '{}' in config file must be a boolean", name),
+                    ))
+                }
+            }
+            continue;
+        }
+
+        for value in values {
+            match scalar_to_string(value) {
+                Some(v) => ret.push(OsString::from(format!("--{}={}", name, v))),
+                None => {
+                    return Err(app.clone().error(
+                        ErrorKind::InvalidValue,
+                        format!(
+                            "key '{}' in config file must be a string, number, boolean or array of them",
+                            name
+                        ),
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Arg;
+
+    fn test_app() -> Command {
+        Command::new("test")
+            .arg(Arg::new("config").long("config"))
+            .arg(Arg::new("timeout").long("timeout"))
+            .arg(Arg::new("cf-token").long("cf-token"))
+            .arg(
+                Arg::new("cf-domain")
+                    .long("cf-domain")
+                    .num_args(1..)
+                    .action(ArgAction::Append),
+            )
+            .arg(
+                Arg::new("verbose")
+                    .long("verbose")
+                    .action(ArgAction::SetTrue),
+            )
+    }
+
+    #[test]
+    fn test_build_arguments() {
+        let config = toml::from_str::<Value>(
+            r#"
+            timeout = 3000
+            verbose = true
+            [cf]
+            token = "file-token"
+            domain = ["a.example.com", "b.example.com"]
+            "#,
+        )
+        .unwrap();
+        let mut entries = vec![];
+        flatten(&config, "", &mut entries);
+
+        let app = test_app();
+        let cli = app
+            .clone()
+            .get_matches_from(["test", "--cf-token", "cli-token"]);
+        let args = build_arguments(&app, &cli, &entries).unwrap();
+        let mut argv = vec![OsString::from("test")];
+        argv.extend(args);
+        argv.extend(["--cf-token", "cli-token"].iter().map(OsString::from));
+        let matches = app.get_matches_from(argv);
+
+        assert_eq!(matches.get_one::<String>("timeout").unwrap(), "3000");
+        assert_eq!(matches.get_one::<String>("cf-token").unwrap(), "cli-token");
+        assert!(matches.get_flag("verbose"));
+        assert_eq!(
+            matches
+                .get_many::<String>("cf-domain")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec!["a.example.com", "b.example.com"]
+        );
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let config = serde_yaml::from_str::<Value>("cf:\n  tokne: abc\n").unwrap();
+        let mut entries = vec![];
+        flatten(&config, "", &mut entries);
+        let app = test_app();
+        let cli = app.clone().get_matches_from(["test"]);
+        let err = build_arguments(&app, &cli, &entries).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnknownArgument);
+        assert!(err.to_string().contains("cf-tokne"));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let config = toml::from_str::<Value>(
+            r#"
+            timeout = 3000
+            cf-token = "global-token"
+            [profile.home.cf]
+            token = "home-token"
+            domain = ["home.example.com"]
+            [profile.office]
+            cf-domain = ["office.example.com"]
+            "#,
+        )
+        .unwrap();
+
+        let (global, profiles) = split_profiles(&config).unwrap();
+        assert_eq!(profiles.len(), 2);
+        let (name, home) = &profiles[0];
+        assert_eq!(name, "home");
+        let merged = merge_entries(&global, home);
+        assert!(merged.contains(&(String::from("timeout"), vec![Value::from(3000)])));
+        assert!(merged.contains(&(String::from("cf-token"), vec![Value::from("home-token")])));
+        assert!(!merged.contains(&(String::from("cf-token"), vec![Value::from("global-token")])));
+
+        let merged = merge_entries(&global, &profiles[1].1);
+        assert!(merged.contains(&(String::from("cf-token"), vec![Value::from("global-token")])));
+
+        let mut selected = profiles.clone();
+        select_profiles(&mut selected, &[String::from("office")]).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].0, "office");
+        assert!(select_profiles(&mut profiles.clone(), &[String::from("shop")]).is_err());
+        assert!(select_profiles(&mut vec![], &[String::from("home")]).is_err());
+        select_profiles(&mut vec![], &[]).unwrap();
+    }
+}
@@ -1,3 +1,5 @@
+use std::ffi::OsString;
+use std::net::{IpAddr, SocketAddr};
 use std::process;
 use std::result;
 use std::str::FromStr;
@@ -5,12 +7,14 @@ use std::sync::atomic::Ordering;
 use std::sync::{atomic, Arc};
 use std::time::Duration;
 
-use clap::{Arg, ArgAction, ArgMatches, Command};
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
 
 use slog::Drain;
 
 use reqwest::{self, ClientBuilder};
 
+mod config;
+
 #[derive(Debug, Clone)]
 pub struct ProgramOptions {
     pub timeout: Duration,
@@ -21,11 +25,26 @@ pub struct ProgramOptions {
     pub proxy_address: String,
     pub proxy_username: String,
     pub proxy_password: String,
+    pub daemon: bool,
+    pub interval: Duration,
+    pub interval_jitter: Duration,
+    pub retry_interval: Duration,
+    pub watch_debounce: Duration,
+    pub watch_netlink: bool,
+    pub state_file: String,
+    pub state_max_age: Duration,
+    pub force: bool,
+    pub dry_run: bool,
+    pub dry_run_format: String,
+    pub detector_mode: DetectorMode,
+    pub detector_order: Vec<String>,
 }
 
 pub type SharedProgramOptions = Arc<ProgramOptions>;
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum HttpMethod {
+    #[default]
     Get,
     Post,
     Put,
@@ -34,6 +53,47 @@ pub enum HttpMethod {
     Head,
 }
 
+impl FromStr for HttpMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "GET" => Ok(HttpMethod::Get),
+            "POST" => Ok(HttpMethod::Post),
+            "PUT" => Ok(HttpMethod::Put),
+            "PATCH" => Ok(HttpMethod::Patch),
+            "DELETE" => Ok(HttpMethod::Delete),
+            "HEAD" => Ok(HttpMethod::Head),
+            _ => Err(format!("unknown HTTP method {}", s)),
+        }
+    }
+}
+
+/// How results of detectors are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DetectorMode {
+    /// Use addresses of all successful detectors
+    #[default]
+    Union,
+    /// Use addresses of the first successful detector
+    First,
+    /// Use IPv4 and IPv6 addresses of the first successful detector of each family
+    PerFamily,
+}
+
+impl FromStr for DetectorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "union" => Ok(DetectorMode::Union),
+            "first" => Ok(DetectorMode::First),
+            "per-family" => Ok(DetectorMode::PerFamily),
+            _ => Err(format!("unknown detector mode {}", s)),
+        }
+    }
+}
+
 pub fn app() -> Command {
     let matches = command!();
     matches
@@ -41,6 +101,21 @@ pub fn app() -> Command {
         .version(crate_version!())
         .about(crate_description!())
         .max_term_width(120)
+        .arg(
+            Arg::new("config")
+                .short('c')
+                .long("config")
+                .value_name("FILE")
+                .help("Load options from TOML or YAML file, keys are the long option names and command line options override them"),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("NAME")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Only run these profiles in configure file, all profiles are run by default"),
+        )
         // .arg(
         //     Arg::new("version")
         //         .short('v')
@@ -94,6 +169,100 @@ pub fn app() -> Command {
                 .long("proxy-password")
                 .help("Set proxy password fo auth"),
         )
+        .arg(
+            Arg::new("daemon")
+                .short('d')
+                .long("daemon")
+                .action(ArgAction::SetTrue)
+                .help("Keep running and detect address periodically, update records only when changed"),
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .value_name("SECONDS")
+                .default_value("300")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Set interval in seconds between detections in daemon mode"),
+        )
+        .arg(
+            Arg::new("interval-jitter")
+                .long("interval-jitter")
+                .value_name("SECONDS")
+                .default_value("30")
+                .help("Add random delay up to this value in seconds to every interval in daemon mode"),
+        )
+        .arg(
+            Arg::new("retry-interval")
+                .long("retry-interval")
+                .value_name("SECONDS")
+                .default_value("30")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Set first retry delay in seconds after failure in daemon mode, doubled on every continuous failure up to --interval"),
+        )
+        .arg(
+            Arg::new("watch-debounce")
+                .long("watch-debounce")
+                .value_name("MILLISECONDS")
+                .default_value("1000")
+                .help("Set delay in miliseconds to wait for more changes after changes are notified by watchers in daemon mode"),
+        )
+        .arg(
+            Arg::new("watch-netlink")
+                .long("watch-netlink")
+                .action(ArgAction::SetTrue)
+                .help("Run detection at once when addresses of network interfaces are changed in daemon mode(Linux only)"),
+        )
+        .arg(
+            Arg::new("detector-mode")
+                .long("detector-mode")
+                .value_name("MODE")
+                .value_parser(["union", "first", "per-family"])
+                .default_value("union")
+                .help("Set how to combine addresses of detectors: all successful detectors(union), the first successful detector(first), or the first successful detector of IPv4 and IPv6 separately(per-family)"),
+        )
+        .arg(
+            Arg::new("detector-order")
+                .long("detector-order")
+                .value_name("DETECTOR")
+                .num_args(1..)
+                .action(ArgAction::Append)
+                .help("Set order of detectors to run(ip, get-ip-by-url, iface, stun, dns-query, upnp, command, file), detectors not listed run after them in this order"),
+        )
+        .arg(
+            Arg::new("state-file")
+                .long("state-file")
+                .value_name("FILE")
+                .help("Save published records into this file and skip updating when address not changed"),
+        )
+        .arg(
+            Arg::new("state-max-age")
+                .long("state-max-age")
+                .value_name("SECONDS")
+                .default_value("86400")
+                .help("Force updating when records in --state-file are older than this value in seconds"),
+        )
+        .arg(
+            Arg::new("force")
+                .short('f')
+                .long("force")
+                .action(ArgAction::SetTrue)
+                .help("Update records even if address not changed"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("Only print records to create, delete and keep without modifying anything"),
+        )
+        .arg(
+            Arg::new("dry-run-format")
+                .long("dry-run-format")
+                .value_name("FORMAT")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .help("Set output format of --dry-run"),
+        )
 }
 
 pub fn unwraper_flag<S>(matches: &ArgMatches, name: S) -> bool
@@ -107,7 +276,7 @@ where
     false
 }
 
-fn generate_options(matches: &ArgMatches) -> ProgramOptions {
+fn create_logger(matches: &ArgMatches) -> slog::Logger {
     let debug_log_on = Arc::new(atomic::AtomicBool::new(unwraper_flag(matches, "verbose")));
     let decorator = slog_term::TermDecorator::new().build();
     let drain = slog_term::FullFormat::new(decorator).build().fuse();
@@ -122,10 +291,15 @@ fn generate_options(matches: &ArgMatches) -> ProgramOptions {
         .build()
         .fuse();
 
+    slog::Logger::root(drain, o!())
+}
+
+pub fn generate_options(matches: &ArgMatches, logger: slog::Logger) -> ProgramOptions {
+    let detector_order = unwraper_multiple_values(matches, "detector-order", &logger, "detector");
     ProgramOptions {
         timeout: Duration::from_millis(unwraper_from_str_or(matches, "timeout", 60000)),
         insecure: unwraper_flag(matches, "insecure"),
-        logger: slog::Logger::root(drain, o!()),
+        logger,
         http_user_agent: unwraper_option_or(
             matches,
             "http-user-agent",
@@ -135,18 +309,104 @@ fn generate_options(matches: &ArgMatches) -> ProgramOptions {
         proxy_address: unwraper_option_or(matches, "proxy", String::default()),
         proxy_username: unwraper_option_or(matches, "proxy-username", String::default()),
         proxy_password: unwraper_option_or(matches, "proxy-password", String::default()),
+        daemon: unwraper_flag(matches, "daemon"),
+        interval: Duration::from_secs(unwraper_from_str_or(matches, "interval", 300)),
+        interval_jitter: Duration::from_secs(unwraper_from_str_or(matches, "interval-jitter", 30)),
+        retry_interval: Duration::from_secs(unwraper_from_str_or(matches, "retry-interval", 30)),
+        watch_debounce: Duration::from_millis(unwraper_from_str_or(
+            matches,
+            "watch-debounce",
+            1000,
+        )),
+        watch_netlink: unwraper_flag(matches, "watch-netlink"),
+        state_file: unwraper_option_or(matches, "state-file", String::default()),
+        state_max_age: Duration::from_secs(unwraper_from_str_or(matches, "state-max-age", 86400)),
+        force: unwraper_flag(matches, "force"),
+        dry_run: unwraper_flag(matches, "dry-run"),
+        dry_run_format: unwraper_option_or(matches, "dry-run-format", String::from("text")),
+        detector_mode: unwraper_from_str_or(matches, "detector-mode", DetectorMode::Union),
+        detector_order,
     }
 }
 
-pub fn parse_options(app: Command) -> (ArgMatches, SharedProgramOptions) {
-    let matches: ArgMatches = app.get_matches();
-    if unwraper_flag(&matches, "version") {
+fn get_matches_with_config(
+    app: &Command,
+    cli_matches: &ArgMatches,
+    entries: &[config::ConfigEntry],
+) -> ArgMatches {
+    let config_args = match config::build_arguments(app, cli_matches, entries) {
+        Ok(v) => v,
+        Err(e) => e.exit(),
+    };
+
+    let mut args: Vec<OsString> = std::env::args_os().take(1).collect();
+    args.extend(config_args);
+    args.extend(std::env::args_os().skip(1));
+    app.clone().get_matches_from(args)
+}
+
+/// Parse options of every profile.
+/// There is only one profile named `default` when no `[profile.<NAME>]` is set in configure file.
+pub fn parse_options(app: Command) -> Vec<(String, ArgMatches, SharedProgramOptions)> {
+    let cli_matches: ArgMatches = app.clone().get_matches();
+    if unwraper_flag(&cli_matches, "version") {
         println!("{}", crate_version!());
         process::exit(0);
     }
 
-    let options = generate_options(&matches);
-    (matches, Arc::new(options))
+    let (global_entries, mut profiles) = match cli_matches.get_one::<String>("config") {
+        Some(path) => match config::load_file(path).and_then(|v| config::split_profiles(&v)) {
+            Ok(v) => v,
+            Err(msg) => app.clone().error(clap::error::ErrorKind::Io, msg).exit(),
+        },
+        None => (vec![], vec![]),
+    };
+
+    let selected: Vec<String> = cli_matches
+        .get_many::<String>("profile")
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default();
+    if let Err(msg) = config::select_profiles(&mut profiles, &selected) {
+        app.clone()
+            .error(clap::error::ErrorKind::InvalidValue, msg)
+            .exit();
+    }
+
+    let matches = get_matches_with_config(&app, &cli_matches, &global_entries);
+    let logger = create_logger(&matches);
+    if profiles.is_empty() {
+        let options = generate_options(&matches, logger);
+        return vec![(String::from("default"), matches, Arc::new(options))];
+    }
+
+    profiles
+        .into_iter()
+        .map(|(name, entries)| {
+            let entries = config::merge_entries(&global_entries, &entries);
+            let matches = get_matches_with_config(&app, &cli_matches, &entries);
+            let options = generate_options(&matches, logger.new(o!("profile" => name.clone())));
+            (name, matches, Arc::new(options))
+        })
+        .collect()
+}
+
+/// Split `HOST:PORT`, `HOST`, `IPv4`, `[IPv6]:PORT` or `IPv6` into host and port.
+pub fn split_host_port(address: &str, default_port: u16) -> (String, u16) {
+    if let Ok(addr) = address.parse::<SocketAddr>() {
+        return (addr.ip().to_string(), addr.port());
+    }
+    if let Ok(addr) = address
+        .trim_matches(|c| c == '[' || c == ']')
+        .parse::<IpAddr>()
+    {
+        return (addr.to_string(), default_port);
+    }
+    if let Some((host, port)) = address.rsplit_once(':') {
+        if let Ok(port) = port.parse::<u16>() {
+            return (host.to_string(), port);
+        }
+    }
+    (address.to_string(), default_port)
 }
 
 pub fn unwraper_from_str_or<T, S>(matches: &ArgMatches, name: S, def: T) -> T
@@ -287,6 +547,20 @@ impl ProgramOptions {
     }
 
     pub fn http<U>(&self, method: HttpMethod, url: U) -> reqwest::RequestBuilder
+    where
+        U: reqwest::IntoUrl,
+    {
+        self.http_with_local_address(method, url, None)
+    }
+
+    /// Create HTTP request whose connection is bound to local address, which can be used to
+    /// force the address family.
+    pub fn http_with_local_address<U>(
+        &self,
+        method: HttpMethod,
+        url: U,
+        local_address: Option<IpAddr>,
+    ) -> reqwest::RequestBuilder
     where
         U: reqwest::IntoUrl,
     {
@@ -296,6 +570,7 @@ impl ProgramOptions {
             .gzip(true)
             .redirect(reqwest::redirect::Policy::limited(32))
             .timeout(self.timeout)
+            .local_address(local_address)
             //.use_rustls_tls()
             ;
         if let Some(p) = self.create_proxy() {
@@ -313,3 +588,88 @@ impl ProgramOptions {
         builder.header("User-Agent", &self.http_user_agent)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(
+            split_host_port("stun.l.google.com:19302", 3478),
+            (String::from("stun.l.google.com"), 19302)
+        );
+        assert_eq!(
+            split_host_port("stun.example.com", 3478),
+            (String::from("stun.example.com"), 3478)
+        );
+        assert_eq!(
+            split_host_port("[2001:db8::1]:3479", 3478),
+            (String::from("2001:db8::1"), 3479)
+        );
+        assert_eq!(
+            split_host_port("2001:db8::1", 53),
+            (String::from("2001:db8::1"), 53)
+        );
+    }
+
+    #[test]
+    fn test_daemon_intervals() {
+        for arg in ["--interval", "--retry-interval"] {
+            assert!(crate::create_app()
+                .try_get_matches_from(["ddns-cli", "-d", arg, "0"])
+                .is_err());
+        }
+
+        let matches = crate::create_app()
+            .try_get_matches_from([
+                "ddns-cli",
+                "-d",
+                "--interval",
+                "60",
+                "--retry-interval",
+                "5",
+            ])
+            .unwrap();
+        let options = generate_options(&matches, slog::Logger::root(slog::Discard, o!()));
+        assert_eq!(options.interval, Duration::from_secs(60));
+        assert_eq!(options.retry_interval, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_http_with_local_address() {
+        use async_std::io::WriteExt;
+
+        async_std::task::block_on(async {
+            let listener = async_std::net::TcpListener::bind("127.0.0.1:0")
+                .await
+                .unwrap();
+            let url = format!("http://{}/", listener.local_addr().unwrap());
+            let server = async_std::task::spawn(async move {
+                let (mut stream, peer) = listener.accept().await.unwrap();
+                crate::test_util::read_http_request(&mut stream).await;
+                let body = peer.ip().to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            });
+
+            // Every address of 127.0.0.0/8 is loopback, so the server sees the bound address
+            let options = crate::test_util::default_options(Duration::from_secs(3));
+            let local_address = IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 2));
+            let peer = options
+                .http_with_local_address(HttpMethod::Get, &url, Some(local_address))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+            assert_eq!(peer, "127.0.0.2");
+            server.await;
+        });
+    }
+}
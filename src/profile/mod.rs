@@ -0,0 +1,387 @@
+use std::time::Instant;
+
+use futures::channel::mpsc;
+use futures::future::{self, BoxFuture, Either, Shared};
+use futures::StreamExt;
+
+use clap::ArgMatches;
+
+use super::daemon;
+use super::detector::{AddressFilter, Detector, Ipv6Composer, Record};
+use super::driver::{DomainPlan, Driver};
+use super::option::{DetectorMode, SharedProgramOptions};
+use super::state::StateCache;
+
+pub type ShutdownSignal = Shared<BoxFuture<'static, ()>>;
+
+/// Merge addresses of one detector into `records` by `mode`, return true when no more detectors
+/// need to run.
+fn merge_records(mode: DetectorMode, records: &mut Vec<Record>, detected: Vec<Record>) -> bool {
+    match mode {
+        DetectorMode::Union => {
+            records.extend(detected);
+            false
+        }
+        DetectorMode::First => {
+            if detected.is_empty() {
+                return false;
+            }
+            records.extend(detected);
+            true
+        }
+        DetectorMode::PerFamily => {
+            let has_a = records.iter().any(|r| matches!(r, Record::A(_)));
+            let has_aaaa = records.iter().any(|r| matches!(r, Record::Aaaa(_)));
+            records.extend(detected.into_iter().filter(|r| match r {
+                Record::A(_) => !has_a,
+                Record::Aaaa(_) => !has_aaaa,
+                _ => true,
+            }));
+            records.iter().any(|r| matches!(r, Record::A(_)))
+                && records.iter().any(|r| matches!(r, Record::Aaaa(_)))
+        }
+    }
+}
+
+/// Prefix of options of address filter applied to records of all detectors.
+pub const GLOBAL_FILTER_PREFIX: &str = "addr";
+
+/// A profile binds its own detectors, drivers and options.
+pub struct Profile {
+    name: String,
+    detectors: Vec<Box<dyn Detector>>,
+    detector_filters: Vec<AddressFilter>,
+    filter: AddressFilter,
+    composer: Ipv6Composer,
+    drivers: Vec<Box<dyn Driver>>,
+    options: SharedProgramOptions,
+    state: Option<StateCache>,
+    plans: Vec<DomainPlan>,
+}
+
+impl Profile {
+    pub fn new(
+        name: String,
+        mut detectors: Vec<Box<dyn Detector>>,
+        mut drivers: Vec<Box<dyn Driver>>,
+        matches: &ArgMatches,
+        mut options: SharedProgramOptions,
+    ) -> Self {
+        // parse command options
+        let filter_logger = options.create_logger("AddressFilter");
+        let mut detector_filters = vec![];
+        for ref mut detector in &mut detectors {
+            detector.as_mut().parse_options(matches, &mut options);
+
+            let mut filter = AddressFilter::default();
+            filter.parse_options(matches, detector.name(), &filter_logger);
+            detector_filters.push(filter);
+        }
+
+        // detectors in --detector-order run first
+        let mut detector_pairs: Vec<(Box<dyn Detector>, AddressFilter)> =
+            detectors.into_iter().zip(detector_filters).collect();
+        for name in &options.detector_order {
+            if !detector_pairs.iter().any(|(d, _)| d.name() == name) {
+                error!(
+                    options.create_logger("Profile"),
+                    "Unknown detector {} in --detector-order", name
+                );
+            }
+        }
+        detector_pairs.sort_by_key(|(d, _)| {
+            options
+                .detector_order
+                .iter()
+                .position(|name| name == d.name())
+                .unwrap_or(options.detector_order.len())
+        });
+        let (detectors, detector_filters) = detector_pairs.into_iter().unzip();
+
+        let mut filter = AddressFilter::default();
+        filter.parse_options(matches, GLOBAL_FILTER_PREFIX, &filter_logger);
+
+        let mut composer = Ipv6Composer::default();
+        composer.parse_options(matches, &options.create_logger("Ipv6Composer"));
+
+        for ref mut driver in &mut drivers {
+            driver.parse_options(matches, &mut options);
+        }
+
+        let state = if options.state_file.is_empty() {
+            None
+        } else {
+            match StateCache::load(&options.state_file) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    let logger = options.create_logger("State");
+                    error!(
+                        logger,
+                        "Load state file {} failed, error: {}", options.state_file, e
+                    );
+                    None
+                }
+            }
+        };
+
+        Profile {
+            name,
+            detectors,
+            detector_filters,
+            filter,
+            composer,
+            drivers,
+            options,
+            state,
+            plans: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn options(&self) -> &SharedProgramOptions {
+        &self.options
+    }
+
+    pub fn plans(&self) -> &[DomainPlan] {
+        &self.plans
+    }
+
+    async fn detect(&mut self) -> Vec<Record> {
+        let mut records: Vec<Record> = vec![];
+        let filter_logger = self.options.create_logger("AddressFilter");
+        for (detector, filter) in self.detectors.iter_mut().zip(self.detector_filters.iter()) {
+            if let Ok(res) = detector.as_mut().run(&mut self.options).await {
+                let mut detector_records = res.clone();
+                let logger = self.options.create_logger(detector.name());
+                filter.apply(&mut detector_records, &logger);
+                // apply global filter here, so detectors whose addresses are all filtered fall back
+                self.filter.apply(&mut detector_records, &filter_logger);
+                if merge_records(self.options.detector_mode, &mut records, detector_records) {
+                    debug!(
+                        logger,
+                        "Skip other detectors by detector mode {:?}", self.options.detector_mode
+                    );
+                    break;
+                }
+            }
+        }
+
+        let logger = self.options.create_logger("Ipv6Composer");
+        self.composer.apply(&mut records, &logger);
+        records.sort();
+        records.dedup();
+        records
+    }
+
+    async fn publish(&mut self, records: &[Record]) -> bool {
+        let logger = self.options.create_logger("State");
+        let mut success = true;
+        for ref mut driver in &mut self.drivers {
+            let keys = driver.state_keys();
+            if let Some(ref state) = self.state {
+                if !self.options.force && state.is_fresh(&keys, records, self.options.state_max_age)
+                {
+                    debug!(
+                        logger,
+                        "Records of {} not changed, skip updating",
+                        keys.join(",")
+                    );
+                    continue;
+                }
+            }
+
+            if (driver.run(&self.options, records).await).is_err() {
+                success = false;
+                continue;
+            }
+
+            if let Some(ref mut state) = self.state {
+                if !keys.is_empty() {
+                    state.update(&keys, records);
+                    if let Err(e) = state.save(&keys) {
+                        error!(
+                            logger,
+                            "Save state file {} failed, error: {}", self.options.state_file, e
+                        );
+                    }
+                }
+            }
+        }
+
+        success
+    }
+
+    async fn plan(&mut self, records: &[Record]) -> bool {
+        let mut success = true;
+        for ref mut driver in &mut self.drivers {
+            match driver.plan(&self.options, records).await {
+                Ok(plans) => self.plans.extend(plans.into_iter().map(|mut p| {
+                    p.profile = self.name.clone();
+                    p
+                })),
+                Err(_) => success = false,
+            }
+        }
+
+        success
+    }
+
+    /// Run once, or keep running until shutdown in daemon mode.
+    pub async fn run(&mut self, shutdown: ShutdownSignal) -> bool {
+        if self.options.dry_run {
+            let records = self.detect().await;
+            return self.plan(&records).await;
+        }
+
+        if !self.options.daemon {
+            let records = self.detect().await;
+            return self.publish(&records).await;
+        }
+
+        self.run_daemon(shutdown).await
+    }
+
+    async fn run_daemon(&mut self, shutdown: ShutdownSignal) -> bool {
+        let logger = self.options.create_logger("Daemon");
+        let mut scheduler = daemon::Scheduler::new(&self.options);
+        let mut published_records: Option<(Vec<Record>, Instant)> = None;
+
+        info!(
+            logger,
+            "Start daemon mode with interval {}s",
+            self.options.interval.as_secs()
+        );
+
+        // The sender is kept so that waiting for changes never ends when there is no watcher.
+        let (trigger, mut changes) = mpsc::unbounded();
+        for detector in &mut self.detectors {
+            detector.watch(&self.options, &trigger);
+        }
+        if self.options.watch_netlink {
+            match daemon::netlink::watch_address_changes(trigger.clone()) {
+                Ok(_) => debug!(logger, "Start watching address changes by netlink"),
+                Err(e) => error!(
+                    logger,
+                    "Watch address changes by netlink failed, error: {}", e
+                ),
+            }
+        }
+
+        loop {
+            let round = async {
+                let records = self.detect().await;
+                if records.is_empty() {
+                    error!(logger, "No address detected");
+                    return false;
+                }
+
+                if let Some((ref last_records, ref published_at)) = published_records {
+                    if !self.options.force
+                        && *last_records == records
+                        && published_at.elapsed() < self.options.state_max_age
+                    {
+                        debug!(logger, "Address not changed, skip updating");
+                        return true;
+                    }
+                }
+
+                if self.publish(&records).await {
+                    published_records = Some((records, Instant::now()));
+                    true
+                } else {
+                    false
+                }
+            };
+
+            let success = match future::select(Box::pin(round), shutdown.clone()).await {
+                Either::Left((success, _)) => success,
+                Either::Right(_) => break,
+            };
+
+            let delay = scheduler.next_delay(success);
+            if !success {
+                info!(
+                    logger,
+                    "Failed {} time(s), retry in {}s",
+                    scheduler.failed_count(),
+                    delay.as_secs()
+                );
+            } else {
+                debug!(logger, "Next detection in {}s", delay.as_secs());
+            }
+
+            let wait = {
+                let changes = &mut changes;
+                let logger = &logger;
+                let debounce = self.options.watch_debounce;
+                async move {
+                    let sleep = Box::pin(async_std::task::sleep(delay));
+                    if let Either::Right(_) = future::select(sleep, changes.next()).await {
+                        // Wait for more changes, files may be written more than once for example
+                        while let Ok(Some(_)) =
+                            async_std::future::timeout(debounce, changes.next()).await
+                        {
+                        }
+                        info!(logger, "Changes are notified, run detection at once");
+                    }
+                }
+            };
+            if let Either::Right(_) = future::select(Box::pin(wait), shutdown.clone()).await {
+                break;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_merge_records() {
+        let a1 = Record::A(Ipv4Addr::new(192, 0, 2, 1));
+        let a2 = Record::A(Ipv4Addr::new(192, 0, 2, 2));
+        let aaaa = Record::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+        let mut records = vec![];
+        assert!(!merge_records(
+            DetectorMode::Union,
+            &mut records,
+            vec![a1.clone()]
+        ));
+        assert!(!merge_records(
+            DetectorMode::Union,
+            &mut records,
+            vec![a2.clone()]
+        ));
+        assert_eq!(records, vec![a1.clone(), a2.clone()]);
+
+        let mut records = vec![];
+        assert!(!merge_records(DetectorMode::First, &mut records, vec![]));
+        assert!(merge_records(
+            DetectorMode::First,
+            &mut records,
+            vec![a1.clone()]
+        ));
+        assert_eq!(records, vec![a1.clone()]);
+
+        let mut records = vec![];
+        assert!(!merge_records(
+            DetectorMode::PerFamily,
+            &mut records,
+            vec![a1.clone()]
+        ));
+        assert!(merge_records(
+            DetectorMode::PerFamily,
+            &mut records,
+            vec![a2, aaaa.clone()]
+        ));
+        assert_eq!(records, vec![a1, aaaa]);
+    }
+}
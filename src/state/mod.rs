@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::detector::Record;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct StateEntry {
+    records: Vec<String>,
+    updated_at: u64,
+}
+
+/// Last successfully published records of every driver and domain.
+#[derive(Debug, Default)]
+pub struct StateCache {
+    path: PathBuf,
+    entries: BTreeMap<String, StateEntry>,
+}
+
+fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn record_strings(records: &[Record]) -> Vec<String> {
+    let mut ret: Vec<String> = records.iter().map(|r| r.to_string()).collect();
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+fn read_entries(path: &Path) -> io::Result<BTreeMap<String, StateEntry>> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+impl StateCache {
+    pub fn load<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(StateCache {
+            path: path.as_ref().to_path_buf(),
+            entries: read_entries(path.as_ref())?,
+        })
+    }
+
+    /// All keys are published with the same records within max age.
+    pub fn is_fresh(&self, keys: &[String], records: &[Record], max_age: Duration) -> bool {
+        if keys.is_empty() {
+            return false;
+        }
+
+        let records = record_strings(records);
+        let now = now_seconds();
+        keys.iter().all(|key| match self.entries.get(key) {
+            Some(entry) => {
+                entry.records == records && now.saturating_sub(entry.updated_at) < max_age.as_secs()
+            }
+            None => false,
+        })
+    }
+
+    pub fn update(&mut self, keys: &[String], records: &[Record]) {
+        let records = record_strings(records);
+        let now = now_seconds();
+        for key in keys {
+            self.entries.insert(
+                key.clone(),
+                StateEntry {
+                    records: records.clone(),
+                    updated_at: now,
+                },
+            );
+        }
+    }
+
+    /// Save state file, entries of other profiles or processes written after loading are kept.
+    pub fn save(&mut self, keys: &[String]) -> io::Result<()> {
+        let mut entries = read_entries(&self.path).unwrap_or_default();
+        for key in keys {
+            if let Some(entry) = self.entries.get(key) {
+                entries.insert(key.clone(), entry.clone());
+            }
+        }
+        self.entries = entries;
+
+        if let Some(dir) = self.path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let content = serde_json::to_string_pretty(&self.entries)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_fresh_and_expired() {
+        let path = std::env::temp_dir().join(format!("ddns-cli-state-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let keys = vec![String::from("cloudflare:zone:home.example.com")];
+        let records = vec![Record::A(Ipv4Addr::new(1, 2, 3, 4))];
+        let mut state = StateCache::load(&path).unwrap();
+        assert!(!state.is_fresh(&keys, &records, Duration::from_secs(60)));
+
+        state.update(&keys, &records);
+        state.save(&keys).unwrap();
+
+        let state = StateCache::load(&path).unwrap();
+        assert!(state.is_fresh(&keys, &records, Duration::from_secs(60)));
+        assert!(!state.is_fresh(&keys, &records, Duration::from_secs(0)));
+        assert!(!state.is_fresh(
+            &keys,
+            &[Record::A(Ipv4Addr::new(1, 2, 3, 5))],
+            Duration::from_secs(60)
+        ));
+        assert!(!state.is_fresh(&[], &records, Duration::from_secs(60)));
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,71 @@
+//! Helpers shared by tests which talk to local servers.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_std::io::{ReadExt, WriteExt};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task::JoinHandle;
+
+use super::option;
+
+/// Default options with logs discarded.
+pub fn default_options(timeout: Duration) -> option::SharedProgramOptions {
+    let matches = option::app().get_matches_from(["ddns-cli"]);
+    let mut options = option::generate_options(&matches, slog::Logger::root(slog::Discard, o!()));
+    options.timeout = timeout;
+    Arc::new(options)
+}
+
+/// Read one HTTP request and return it with the body.
+pub async fn read_http_request(stream: &mut TcpStream) -> String {
+    let mut data = vec![];
+    let mut buffer = [0_u8; 1024];
+    loop {
+        let size = stream.read(&mut buffer).await.unwrap();
+        data.extend_from_slice(&buffer[..size]);
+        let text = String::from_utf8_lossy(&data).to_string();
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let content_length = text[..header_end]
+                .lines()
+                .find_map(|l| {
+                    let (name, value) = l.split_once(':')?;
+                    if name.eq_ignore_ascii_case("content-length") {
+                        value.trim().parse::<usize>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(0);
+            if data.len() >= header_end + 4 + content_length {
+                return text;
+            }
+        }
+        if size == 0 {
+            return text;
+        }
+    }
+}
+
+/// Local HTTP server which replies `responses` of status and body in order, and returns requests.
+pub async fn http_stand_in(responses: Vec<(u16, String)>) -> (SocketAddr, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = async_std::task::spawn(async move {
+        let mut requests = vec![];
+        for (status, body) in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            requests.push(read_http_request(&mut stream).await);
+            let response = format!(
+                "HTTP/1.1 {} Stand-in\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+        requests
+    });
+    (address, server)
+}